@@ -1,12 +1,20 @@
+use std::fmt;
 use std::vec::Vec;
+use super::constraints::UriTemplateConstraint;
 use super::types::{UriTemplate, UriTemplateComponent, UriTemplateOperator, UriTemplateVariable};
 
 
-#[derive(Clone,PartialEq,Eq)]
+#[derive(Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
 pub struct UriTemplateBuilder {
     components: Vec<UriTemplateComponent>,
 }
 
+impl Default for UriTemplateBuilder {
+    fn default() -> UriTemplateBuilder {
+        UriTemplateBuilder::new()
+    }
+}
+
 impl UriTemplateBuilder {
     pub fn new() -> UriTemplateBuilder {
         UriTemplateBuilder {
@@ -40,25 +48,67 @@ impl UriTemplateBuilder {
 }
 
 
-#[derive(Copy,Clone,PartialEq,Eq)]
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
 pub enum UriTemplateModifier {
     Prefix(u32),
     Explode,
     ExplodePrefix(u32),
 }
 
+/// Displays as the modifier's template syntax, e.g. `:7` or `*`.
+impl fmt::Display for UriTemplateModifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UriTemplateModifier::Prefix(prefix) => f.pad(&format!(":{}", prefix)),
+            UriTemplateModifier::Explode => f.pad("*"),
+            UriTemplateModifier::ExplodePrefix(prefix) => f.pad(&format!("*:{}", prefix)),
+        }
+    }
+}
+
+#[derive(Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
 pub struct UriTemplateComponentBuilder {
     variables: Vec<UriTemplateVariable>,
 }
 
 impl UriTemplateComponentBuilder {
-    pub fn variable(mut self, name: &str, modifier: Option<UriTemplateModifier>) -> UriTemplateComponentBuilder {
-        self.variables.push(match modifier {
+    fn new_variable(name: &str, modifier: Option<UriTemplateModifier>) -> UriTemplateVariable {
+        match modifier {
             None => UriTemplateVariable::new_simple(name.to_string()),
             Some(UriTemplateModifier::Prefix(prefix)) => UriTemplateVariable::new_prefix(name.to_string(), prefix),
             Some(UriTemplateModifier::Explode) => UriTemplateVariable::new_explode(name.to_string()),
             Some(UriTemplateModifier::ExplodePrefix(prefix)) => UriTemplateVariable::new_explode_prefix(name.to_string(), prefix)
-        });
+        }
+    }
+
+    pub fn variable(mut self, name: &str, modifier: Option<UriTemplateModifier>) -> UriTemplateComponentBuilder {
+        self.variables.push(UriTemplateComponentBuilder::new_variable(name, modifier));
+        self
+    }
+
+    /// Adds a variable with a constraint on its values, see
+    /// `UriTemplateConstraint`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `modifier` is a `Prefix` or `ExplodePrefix`, see
+    /// `UriTemplateVariable::with_constraint`.
+    pub fn variable_with_constraint(mut self, name: &str, modifier: Option<UriTemplateModifier>, constraint: UriTemplateConstraint) -> UriTemplateComponentBuilder {
+        self.variables.push(UriTemplateComponentBuilder::new_variable(name, modifier).with_constraint(constraint));
+        self
+    }
+
+    /// Adds a variable with a default value, see
+    /// `UriTemplateVariable::with_default`.
+    pub fn variable_with_default(mut self, name: &str, modifier: Option<UriTemplateModifier>, default: &str) -> UriTemplateComponentBuilder {
+        self.variables.push(UriTemplateComponentBuilder::new_variable(name, modifier).with_default(default.to_string()));
+        self
+    }
+
+    /// Adds a prebuilt variable, such as one with both a constraint and a
+    /// default value.
+    pub fn varspec(mut self, variable: UriTemplateVariable) -> UriTemplateComponentBuilder {
+        self.variables.push(variable);
         self
     }
 }
@@ -67,7 +117,7 @@ impl UriTemplateComponentBuilder {
 #[cfg(test)]
 mod test {
     use super::super::UriTemplateBuilder;
-    use super::super::{UriTemplateOperator, UriTemplateModifier};
+    use super::super::{UriTemplate, UriTemplateConstraint, UriTemplateOperator, UriTemplateModifier, UriTemplateParseOptions};
 
     #[test]
     fn test_1() {
@@ -118,4 +168,28 @@ mod test {
         assert_eq!(s1, "http://example.com/{/splat*}{?foo,bar,hash:7}");
         assert_eq!(s1, s2);
     }
+
+    #[test]
+    fn test_constraint_round_trip() {
+        let t = UriTemplateBuilder::new()
+            .literal("/users/")
+            .component(None, |c| {
+                c.variable_with_constraint("id", None, UriTemplateConstraint::Int)
+                 .variable_with_constraint("tags", Some(UriTemplateModifier::Explode), UriTemplateConstraint::Uuid)
+            })
+            .into_uri_template();
+        let s = t.to_template_string();
+
+        assert_eq!(s, "/users/{id:int,tags*:uuid}");
+        assert_eq!(UriTemplate::parse_with_options(&s, &UriTemplateParseOptions::new().strict(false)), Ok(t));
+    }
+
+    #[test]
+    #[should_panic(expected = "prefix modifier")]
+    fn test_constraint_with_prefix() {
+        UriTemplateBuilder::new()
+            .component(None, |c| {
+                c.variable_with_constraint("id", Some(UriTemplateModifier::ExplodePrefix(3)), UriTemplateConstraint::Int)
+            });
+    }
 }
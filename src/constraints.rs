@@ -0,0 +1,169 @@
+use std::fmt;
+use std::vec::Vec;
+
+use super::expanding::UriTemplateExpansionError;
+use super::types::{UriTemplate, UriTemplateComponent, UriTemplateValue, UriTemplateVars};
+
+
+/// A constraint on the values of a variable, written `{id:int}`. This is an
+/// extension to RFC 6570, which templates only accept when parsed with
+/// `UriTemplateParseOptions::strict(false)`.
+///
+/// Constraints restrict what `UriTemplate::match_uri` accepts and can be
+/// checked before expansion with `UriTemplate::check_constraints`, or
+/// during it with `UriTemplateExpansionOptions::check_constraints`. They
+/// apply to pct-decoded values, and to each item of a list.
+#[derive(Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub enum UriTemplateConstraint {
+    /// An optional `-` followed by ASCII digits, written `int`.
+    Int,
+    /// A UUID in its hyphenated hex form, in either case, written `uuid`.
+    Uuid,
+    /// One of the given values, written `(a|b|c)`.
+    OneOf(Vec<String>),
+    /// A constraint checked by a predicate given to `match_uri_with` or
+    /// `check_constraints`, written as its name, e.g. `slug`.
+    Custom(String),
+}
+
+impl UriTemplateConstraint {
+    /// Whether `value` satisfies the constraint. `Custom` constraints are
+    /// checked by calling `custom` with their name and `value`.
+    pub fn is_satisfied_by<F: Fn(&str, &str) -> bool>(&self, value: &str, custom: F) -> bool {
+        match *self {
+            UriTemplateConstraint::Int => {
+                let digits = value.strip_prefix('-').unwrap_or(value);
+                !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+            },
+            UriTemplateConstraint::Uuid => {
+                value.len() == 36 && value.bytes().enumerate().all(|(i, b)| match i {
+                    8 | 13 | 18 | 23 => b == b'-',
+                    _ => b.is_ascii_hexdigit(),
+                })
+            },
+            UriTemplateConstraint::OneOf(ref values) => values.iter().any(|v| v == value),
+            UriTemplateConstraint::Custom(ref name) => custom(name, value),
+        }
+    }
+}
+
+/// Displays as the constraint's template syntax, e.g. `int` or `(a|b)`.
+impl fmt::Display for UriTemplateConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UriTemplateConstraint::Int => f.pad("int"),
+            UriTemplateConstraint::Uuid => f.pad("uuid"),
+            UriTemplateConstraint::OneOf(ref values) => f.pad(&format!("({})", values.join("|"))),
+            UriTemplateConstraint::Custom(ref name) => f.pad(name),
+        }
+    }
+}
+
+
+// Checks the values of the constrained variables of `components`, or their
+// default values when undefined, see `UriTemplate::check_constraints`.
+pub(crate) fn check_components<V: UriTemplateVars + ?Sized, F: Fn(&str, &str) -> bool>(components: &[UriTemplateComponent], values: &V, custom: F) -> Result<(), UriTemplateExpansionError> {
+    for component in components.iter() {
+        if let UriTemplateComponent::Variable(_, ref variables) = *component {
+            for v in variables.iter() {
+                let constraint = match v.constraint() {
+                    Some(constraint) => constraint,
+                    None => continue,
+                };
+                let satisfied = match values.uri_template_value(v.name()).as_deref().filter(|value| !value.is_undefined()) {
+                    Some(UriTemplateValue::String(string)) => constraint.is_satisfied_by(string, &custom),
+                    Some(UriTemplateValue::List(strings)) => strings.iter().all(|string| constraint.is_satisfied_by(string, &custom)),
+                    Some(UriTemplateValue::AssociativeArray(_)) => true,
+                    None => v.default_value().map(|default| constraint.is_satisfied_by(default, &custom)).unwrap_or(true),
+                };
+                if !satisfied {
+                    return Err(UriTemplateExpansionError::ConstraintViolation(v.name().to_string()));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl UriTemplate {
+    /// Checks that the values of constrained variables satisfy their
+    /// constraints, as a validation before expanding the template. Undefined
+    /// variables are checked by their default value, if any, and
+    /// associative arrays are not checked. `custom` checks `Custom`
+    /// constraints, see `UriTemplateConstraint::is_satisfied_by`.
+    ///
+    /// Fails with `ConstraintViolation` naming the first variable whose
+    /// value does not satisfy its constraint.
+    pub fn check_constraints<V: UriTemplateVars + ?Sized, F: Fn(&str, &str) -> bool>(&self, values: &V, custom: F) -> Result<(), UriTemplateExpansionError> {
+        check_components(self.components(), values, custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{UriTemplate, UriTemplateBuilder, UriTemplateExpansionError, UriTemplateExpansionOptions, UriTemplateParseOptions, UriTemplateValues, UriTemplateVariable};
+    use super::UriTemplateConstraint;
+
+    #[test]
+    fn test_is_satisfied_by() {
+        let none = |_: &str, _: &str| false;
+        assert!(UriTemplateConstraint::Int.is_satisfied_by("42", none));
+        assert!(UriTemplateConstraint::Int.is_satisfied_by("-7", none));
+        assert!(!UriTemplateConstraint::Int.is_satisfied_by("-", none));
+        assert!(!UriTemplateConstraint::Int.is_satisfied_by("me", none));
+        assert!(UriTemplateConstraint::Uuid.is_satisfied_by("123e4567-e89b-12d3-A456-426614174000", none));
+        assert!(!UriTemplateConstraint::Uuid.is_satisfied_by("123e4567e89b12d3a456426614174000", none));
+        let one_of = UriTemplateConstraint::OneOf(vec!("json".to_string(), "xml".to_string()));
+        assert!(one_of.is_satisfied_by("xml", none));
+        assert!(!one_of.is_satisfied_by("yaml", none));
+        let custom = UriTemplateConstraint::Custom("slug".to_string());
+        assert!(!custom.is_satisfied_by("a-b", none));
+        assert!(custom.is_satisfied_by("a-b", |name, value| name == "slug" && !value.contains(' ')));
+    }
+
+    #[test]
+    fn test_check_constraints() {
+        let options = UriTemplateParseOptions::new().strict(false);
+        let t = UriTemplate::parse_with_options("/users/{id:int}{?tags*:(a|b),fields}", &options).unwrap();
+        let mut v = UriTemplateValues::new();
+        v.set("id", "42");
+        v.set("tags", ["a", "b"].as_ref());
+        assert_eq!(t.check_constraints(&v, |_, _| false), Ok(()));
+        v.set("tags", ["a", "c"].as_ref());
+        assert_eq!(t.check_constraints(&v, |_, _| false), Err(UriTemplateExpansionError::ConstraintViolation("tags".to_string())));
+        v.set("id", "me");
+        assert_eq!(t.check_constraints(&v, |_, _| false), Err(UriTemplateExpansionError::ConstraintViolation("id".to_string())));
+        assert_eq!(t.check_constraints(&UriTemplateValues::new(), |_, _| false), Ok(()));
+    }
+
+    #[test]
+    fn test_check_constraints_defaults() {
+        let t = UriTemplateBuilder::new()
+            .component(None, |c| c.varspec(UriTemplateVariable::new_simple("format".to_string())
+                .with_constraint(UriTemplateConstraint::Custom("format".to_string()))
+                .with_default("xml".to_string())))
+            .into_uri_template();
+        assert_eq!(t.check_constraints(&UriTemplateValues::new(), |_, value| value == "json"), Err(UriTemplateExpansionError::ConstraintViolation("format".to_string())));
+        assert_eq!(t.check_constraints(&UriTemplateValues::new(), |_, value| value == "xml"), Ok(()));
+    }
+
+    #[test]
+    fn test_expansion_check_constraints() {
+        let options = UriTemplateParseOptions::new().strict(false);
+        let t = UriTemplate::parse_with_options("/users/{id:int}{?format:(json|csv)=json,s:slug}", &options).unwrap();
+        let check = UriTemplateExpansionOptions::new().check_constraints(true);
+        let mut v = UriTemplateValues::new();
+        v.set("id", "42");
+        v.set("s", "Not A Slug");
+        assert_eq!(t.to_string_with_options(&v, &check), Ok("/users/42?format=json&s=Not%20A%20Slug".to_string()));
+        v.set("format", "xml");
+        assert_eq!(t.to_string_with_options(&v, &check), Err(UriTemplateExpansionError::ConstraintViolation("format".to_string())));
+        assert_eq!(t.to_string_with_options(&v, &UriTemplateExpansionOptions::new()), Ok("/users/42?format=xml&s=Not%20A%20Slug".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "does not satisfy its constraint")]
+    fn test_invalid_default() {
+        let _ = UriTemplateVariable::new_simple("id".to_string()).with_default("abc".to_string()).with_constraint(UriTemplateConstraint::Int);
+    }
+}
@@ -0,0 +1,174 @@
+use std::error::Error;
+use std::fmt;
+use std::vec::Vec;
+
+use super::types::{UriTemplate, UriTemplateValue, UriTemplateVars};
+
+
+#[derive(Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub enum UriTemplateMatchError {
+    /// The URI does not match the template.
+    NoMatch,
+    /// The named variable is undefined but a value is required.
+    MissingValue(String),
+    /// The value of the named variable cannot be converted, with a
+    /// description of why.
+    InvalidValue(String, String),
+}
+
+impl fmt::Display for UriTemplateMatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UriTemplateMatchError::NoMatch => write!(f, "URI does not match the template"),
+            UriTemplateMatchError::MissingValue(ref name) => write!(f, "missing value for {:?}", name),
+            UriTemplateMatchError::InvalidValue(ref name, ref reason) => write!(f, "invalid value for {:?}: {}", name, reason),
+        }
+    }
+}
+
+impl Error for UriTemplateMatchError {}
+
+
+/// Conversion from the value of a single variable, the inverse of
+/// `ToUriTemplateValue`. Implemented for the same scalar types through
+/// `FromStr`, and for `Option<T>` and `Vec<T>`.
+///
+/// Other `FromStr` types, such as enums or UUIDs, can implement it with
+/// `UriTemplateValue::parse`, or be converted by
+/// `#[derive(FromUriTemplateValues)]` with `#[uritemplate(from_str)]`.
+pub trait FromUriTemplateValue: Sized {
+    /// Converts `value`, describing why if it cannot be converted.
+    fn from_uri_template_value(value: &UriTemplateValue) -> Result<Self, String>;
+
+    /// The value for an undefined variable, or `None` if one is required.
+    fn from_undefined() -> Option<Self> {
+        None
+    }
+}
+
+macro_rules! from_uri_template_value_via_from_str {
+    ($($t:ty),*) => {
+        $(
+            impl FromUriTemplateValue for $t {
+                fn from_uri_template_value(value: &UriTemplateValue) -> Result<$t, String> {
+                    value.parse()
+                }
+            }
+        )*
+    };
+}
+
+from_uri_template_value_via_from_str!(String, bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+/// An undefined variable converts to `None`.
+impl<T: FromUriTemplateValue> FromUriTemplateValue for Option<T> {
+    fn from_uri_template_value(value: &UriTemplateValue) -> Result<Option<T>, String> {
+        T::from_uri_template_value(value).map(Some)
+    }
+
+    fn from_undefined() -> Option<Option<T>> {
+        Some(None)
+    }
+}
+
+/// Converts each item of a list, or a single value as a list of one item.
+/// `match_uri` already splits unexploded lists at their commas. An
+/// undefined variable converts to an empty `Vec`.
+impl<T: FromUriTemplateValue> FromUriTemplateValue for Vec<T> {
+    fn from_uri_template_value(value: &UriTemplateValue) -> Result<Vec<T>, String> {
+        let item = |item: &str| T::from_uri_template_value(&UriTemplateValue::String(item.to_string()));
+        match *value {
+            UriTemplateValue::String(ref string) => item(string).map(|item| vec!(item)),
+            UriTemplateValue::List(ref items) => items.iter().map(|s| item(s)).collect(),
+            UriTemplateValue::AssociativeArray(_) => Err("expected a list".to_string()),
+        }
+    }
+
+    fn from_undefined() -> Option<Vec<T>> {
+        Some(Vec::new())
+    }
+}
+
+
+/// Conversion from the values of a template's variables into a typed
+/// struct, usually through `#[derive(FromUriTemplateValues)]` from the
+/// `uritemplates-macros` crate. See `UriTemplate::match_into`.
+pub trait FromUriTemplateValues: Sized {
+    /// Converts `values`, failing with `MissingValue` or `InvalidValue`
+    /// naming the first variable that cannot be converted.
+    fn from_uri_template_values<V: UriTemplateVars + ?Sized>(values: &V) -> Result<Self, UriTemplateMatchError>;
+}
+
+
+impl UriTemplate {
+    /// Matches `uri` against the template as `match_uri` does and converts
+    /// the values into `T`. Fails with `NoMatch` if the URI does not match,
+    /// and otherwise with the error naming the variable that could not be
+    /// converted.
+    pub fn match_into<T: FromUriTemplateValues>(&self, uri: &str) -> Result<T, UriTemplateMatchError> {
+        let values = self.match_uri(uri).ok_or(UriTemplateMatchError::NoMatch)?;
+        T::from_uri_template_values(&values)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::{UriTemplate, UriTemplateValue, UriTemplateVars};
+    use super::{FromUriTemplateValue, FromUriTemplateValues, UriTemplateMatchError};
+
+    fn string(s: &str) -> UriTemplateValue {
+        UriTemplateValue::String(s.to_string())
+    }
+
+    #[test]
+    fn test_from_uri_template_value() {
+        assert_eq!(u32::from_uri_template_value(&string("42")), Ok(42));
+        assert_eq!(u32::from_uri_template_value(&string("-1")), Err("invalid digit found in string".to_string()));
+        assert_eq!(String::from_uri_template_value(&string("a b")), Ok("a b".to_string()));
+        assert_eq!(bool::from_uri_template_value(&UriTemplateValue::List(vec!())), Err("expected a single value".to_string()));
+        assert_eq!(Option::<u8>::from_uri_template_value(&string("7")), Ok(Some(7)));
+        assert_eq!(Option::<u8>::from_undefined(), Some(None));
+        assert_eq!(u8::from_undefined(), None);
+        assert_eq!(Vec::<u8>::from_uri_template_value(&string("1")), Ok(vec!(1)));
+        assert_eq!(Vec::<String>::from_uri_template_value(&string("a,b")), Ok(vec!("a,b".to_string())));
+        assert_eq!(Vec::<u8>::from_uri_template_value(&UriTemplateValue::List(vec!("3".to_string()))), Ok(vec!(3)));
+        assert_eq!(Vec::<u8>::from_undefined(), Some(vec!()));
+    }
+
+    #[derive(Debug,PartialEq)]
+    struct User {
+        id: u64,
+        fields: Vec<String>,
+    }
+
+    impl FromUriTemplateValues for User {
+        fn from_uri_template_values<V: UriTemplateVars + ?Sized>(values: &V) -> Result<User, UriTemplateMatchError> {
+            let id = match values.uri_template_value("id") {
+                Some(value) => u64::from_uri_template_value(&value).map_err(|e| UriTemplateMatchError::InvalidValue("id".to_string(), e))?,
+                None => return Err(UriTemplateMatchError::MissingValue("id".to_string())),
+            };
+            let fields = match values.uri_template_value("fields") {
+                Some(value) => Vec::from_uri_template_value(&value).map_err(|e| UriTemplateMatchError::InvalidValue("fields".to_string(), e))?,
+                None => vec!(),
+            };
+            Ok(User { id, fields })
+        }
+    }
+
+    #[test]
+    fn test_match_into() {
+        let t = UriTemplate::parse("/users/{id}{?fields*}").unwrap();
+        assert_eq!(t.match_into("/users/42?fields=name&fields=email"), Ok(User { id: 42, fields: vec!("name".to_string(), "email".to_string()) }));
+        assert_eq!(t.match_into("/users/42"), Ok(User { id: 42, fields: vec!() }));
+        let t = UriTemplate::parse("/users/{id}{?fields}").unwrap();
+        assert_eq!(t.match_into("/users/42?fields=name,email"), Ok(User { id: 42, fields: vec!("name".to_string(), "email".to_string()) }));
+        assert_eq!(t.match_into("/users/42?fields=a%2Cb"), Ok(User { id: 42, fields: vec!("a,b".to_string()) }));
+        assert_eq!(t.match_into::<User>("/users/me"), Err(UriTemplateMatchError::InvalidValue("id".to_string(), "invalid digit found in string".to_string())));
+        assert_eq!(t.match_into::<User>("/users/"), Err(UriTemplateMatchError::InvalidValue("id".to_string(), "cannot parse integer from empty string".to_string())));
+        let t = UriTemplate::parse("/users{/id}").unwrap();
+        assert_eq!(t.match_into::<User>("/users"), Err(UriTemplateMatchError::MissingValue("id".to_string())));
+        assert_eq!(t.match_into::<User>("/posts/42"), Err(UriTemplateMatchError::NoMatch));
+        assert_eq!(UriTemplateMatchError::InvalidValue("id".to_string(), "too large".to_string()).to_string(), "invalid value for \"id\": too large");
+    }
+}
@@ -0,0 +1,232 @@
+use std::str;
+
+use super::parsing;
+use super::types::UriTemplateOperator;
+
+
+const UNRESERVED: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-._~";
+const RESERVED: &str = ":/?#[]@!$&'()*+,;=";
+const GEN_DELIMS: &str = ":/?#[]@";
+const FORM: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789*-._";
+
+/// The set of ASCII characters that expansion leaves unescaped; all other
+/// bytes of a value's UTF-8 encoding are pct-encoded.
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub struct EscapeSet {
+    allowed: u128,
+    pct_encoded: bool,
+    space_as_plus: bool,
+}
+
+impl EscapeSet {
+    /// The unreserved characters, which RFC 6570 leaves unescaped for most
+    /// operators.
+    pub fn unreserved() -> EscapeSet {
+        EscapeSet::from_chars(UNRESERVED)
+    }
+
+    /// The unreserved and reserved characters and pct-encoded triplets,
+    /// which RFC 6570 leaves unescaped for `+` and `#` and in literals.
+    pub fn reserved() -> EscapeSet {
+        EscapeSet::unreserved().with_chars(RESERVED).allow_pct_encoded(true)
+    }
+
+    /// ASCII alphanumerics and `*-._`, with spaces written as `+`, as for
+    /// `application/x-www-form-urlencoded`. See
+    /// `UriTemplateExpansionOptions::form_encoded`.
+    pub fn form_urlencoded() -> EscapeSet {
+        EscapeSet { space_as_plus: true, ..EscapeSet::from_chars(FORM) }
+    }
+
+    /// The ASCII characters for which `predicate` returns true.
+    pub fn from_predicate<F: Fn(u8) -> bool>(predicate: F) -> EscapeSet {
+        let mut allowed = 0;
+        for b in 0..128u8 {
+            if predicate(b) {
+                allowed |= 1 << b;
+            }
+        }
+        EscapeSet {
+            allowed,
+            pct_encoded: false,
+            space_as_plus: false,
+        }
+    }
+
+    /// The ASCII characters in `chars`. Non-ASCII characters are ignored,
+    /// since they are always pct-encoded.
+    pub fn from_chars(chars: &str) -> EscapeSet {
+        EscapeSet::from_predicate(|_| false).with_chars(chars)
+    }
+
+    /// Adds the ASCII characters in `chars` to the set.
+    pub fn with_chars(mut self, chars: &str) -> EscapeSet {
+        for b in chars.bytes().filter(|b| b.is_ascii()) {
+            self.allowed |= 1 << b;
+        }
+        self
+    }
+
+    /// Removes the characters in `chars` from the set.
+    pub fn without_chars(mut self, chars: &str) -> EscapeSet {
+        for b in chars.bytes().filter(|b| b.is_ascii()) {
+            self.allowed &= !(1 << b);
+        }
+        self
+    }
+
+    /// Whether a `%` followed by two hex digits is left as it is rather than
+    /// having its `%` encoded as `%25`.
+    pub fn allow_pct_encoded(mut self, allow: bool) -> EscapeSet {
+        self.pct_encoded = allow;
+        self
+    }
+
+    pub fn contains(&self, b: u8) -> bool {
+        b.is_ascii() && self.allowed & (1 << b) != 0
+    }
+
+    // Whether values may bring in URI structure: a gen-delim or an existing
+    // pct-encoded triplet.
+    pub(crate) fn allows_delimiters(&self) -> bool {
+        self.pct_encoded || GEN_DELIMS.bytes().any(|b| self.contains(b))
+    }
+}
+
+
+// The escape set RFC 6570 prescribes for an operator.
+pub(crate) fn default_escape_set(operator: Option<UriTemplateOperator>) -> EscapeSet {
+    match operator {
+        Some(UriTemplateOperator::ReservedCharacter) | Some(UriTemplateOperator::Fragment) => EscapeSet::reserved(),
+        _ => EscapeSet::unreserved(),
+    }
+}
+
+// Where expansion is written: a `String`, or something that only looks at
+// the output, such as a count of its bytes.
+pub(crate) trait Sink {
+    fn push_str(&mut self, s: &str);
+}
+
+impl Sink for String {
+    fn push_str(&mut self, s: &str) {
+        String::push_str(self, s);
+    }
+}
+
+
+#[derive(Copy,Clone)]
+pub(crate) struct Escaper {
+    pub(crate) set: EscapeSet,
+    pub(crate) lowercase_hex: bool,
+    // Whether to leave ucschar code points unescaped, as in an IRI.
+    pub(crate) iri: bool,
+    // Whether to leave iprivate code points unescaped, only allowed in the
+    // query of an IRI.
+    pub(crate) iprivate: bool,
+}
+
+impl Escaper {
+    fn passes(&self, c: char) -> bool {
+        self.iri && (parsing::is_ucschar(c) || (self.iprivate && parsing::is_iprivate(c)))
+    }
+
+    // Writes `string` to `out`, escaped. Runs of bytes that are left as they
+    // are are written in one piece.
+    pub(crate) fn escape<S: Sink + ?Sized>(&self, string: &str, out: &mut S) {
+        let input = string.as_bytes();
+        let mut start = 0;
+        let mut i = 0;
+        while i < input.len() {
+            let byte = input[i];
+            if !byte.is_ascii() {
+                let c = string[i..].chars().next().unwrap();
+                let end = i + c.len_utf8();
+                if !self.passes(c) {
+                    out.push_str(&string[start..i]);
+                    for byte in input[i..end].iter() {
+                        self.push_pct_encoded(out, *byte);
+                    }
+                    start = end;
+                }
+                i = end;
+            } else if byte == b'%' && self.set.pct_encoded && i + 2 < input.len() && input[i + 1].is_ascii_hexdigit() && input[i + 2].is_ascii_hexdigit() {
+                i += 3;
+            } else if byte == b' ' && self.set.space_as_plus {
+                out.push_str(&string[start..i]);
+                out.push_str("+");
+                i += 1;
+                start = i;
+            } else if self.set.contains(byte) {
+                i += 1;
+            } else {
+                out.push_str(&string[start..i]);
+                self.push_pct_encoded(out, byte);
+                i += 1;
+                start = i;
+            }
+        }
+        out.push_str(&string[start..]);
+    }
+
+    fn push_pct_encoded<S: Sink + ?Sized>(&self, out: &mut S, byte: u8) {
+        let digits = if self.lowercase_hex { b"0123456789abcdef" } else { b"0123456789ABCDEF" };
+        let triplet = [b'%', digits[(byte >> 4) as usize], digits[(byte & 0xF) as usize]];
+        out.push_str(str::from_utf8(&triplet).unwrap());
+    }
+
+    // Escapes a literal that starts `in_query` or `in_fragment`, allowing
+    // iprivate only in the part of it that is in the query.
+    pub(crate) fn escape_literal<S: Sink + ?Sized>(&self, literal: &str, in_query: bool, in_fragment: bool, out: &mut S) {
+        if !self.iri {
+            return self.escape(literal, out);
+        }
+        let fragment = if in_fragment { 0 } else { literal.find('#').unwrap_or(literal.len()) };
+        let query = if in_query { 0 } else { literal[..fragment].find('?').unwrap_or(fragment) };
+        let escaper = |iprivate| Escaper { iprivate, ..*self };
+        escaper(false).escape(&literal[..query], out);
+        escaper(true).escape(&literal[query..fragment], out);
+        escaper(false).escape(&literal[fragment..], out);
+    }
+}
+
+
+/// Converts an IRI, such as one expanded with
+/// `UriTemplateExpansionOptions::iri`, to a URI by pct-encoding the UTF-8
+/// encoding of every non-ASCII character (RFC 3987 section 3.1). ASCII
+/// characters, including any existing pct-encoding, are left as they are.
+pub fn iri_to_uri(iri: &str) -> String {
+    let mut s = String::with_capacity(iri.len());
+    for byte in iri.bytes() {
+        if byte.is_ascii() {
+            s.push(byte as char);
+        } else {
+            s.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    s
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{EscapeSet, iri_to_uri};
+
+    #[test]
+    fn test_escape_sets() {
+        let unreserved = EscapeSet::unreserved();
+        assert!(unreserved.contains(b'~'));
+        assert!(!unreserved.contains(b':'));
+        assert!(!unreserved.contains(0xC3));
+        assert!(EscapeSet::reserved().contains(b':'));
+        assert_eq!(EscapeSet::from_predicate(|b| b.is_ascii_digit()), EscapeSet::from_chars("0123456789"));
+        assert_eq!(unreserved.with_chars(":").without_chars(":"), unreserved);
+        assert_eq!(EscapeSet::from_chars("é"), EscapeSet::from_chars(""));
+    }
+
+    #[test]
+    fn test_iri_to_uri() {
+        assert_eq!(iri_to_uri("https://example.com/café/%C3%A9?q=\u{E000}"), "https://example.com/caf%C3%A9/%C3%A9?q=%EE%80%80");
+        assert_eq!(iri_to_uri("/plain?a=b"), "/plain?a=b");
+    }
+}
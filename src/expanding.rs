@@ -0,0 +1,953 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+use super::constraints;
+use super::escaping::{self, EscapeSet, Sink};
+use super::types::{UriTemplate, UriTemplateComponent, UriTemplateOperator, UriTemplateVars};
+#[cfg(feature = "idna")]
+use super::types::UriTemplateValue;
+
+
+/// How `UriTemplateExpansionOptions::dot_segments` treats values that
+/// would add `.` or `..` segments, or `/`, to the path.
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub enum UriTemplateDotSegments {
+    /// Expand them as RFC 6570 specifies.
+    Allow,
+    /// Fail with `DotSegment` or `PathSeparator`.
+    Reject,
+}
+
+
+/// How `UriTemplateExpansionOptions::untrusted` treats untrusted values in
+/// expressions that would leave URI delimiters in them unescaped.
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub enum UriTemplateUntrustedPolicy {
+    /// Expand them like any other value.
+    Allow,
+    /// Fail with `UntrustedValue`.
+    Reject,
+    /// Leave only the characters in the set unescaped.
+    Restrict(EscapeSet),
+}
+
+
+/// Options for `UriTemplate::to_string_with_options`. The defaults expand
+/// exactly as RFC 6570 specifies, except for values marked untrusted (see
+/// `untrusted`).
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub struct UriTemplateExpansionOptions {
+    pub(crate) query_aware: bool,
+    pub(crate) form_encoded: bool,
+    pub(crate) lowercase_hex: bool,
+    pub(crate) iri: bool,
+    pub(crate) dot_segments: UriTemplateDotSegments,
+    pub(crate) untrusted: UriTemplateUntrustedPolicy,
+    pub(crate) max_list_length: Option<usize>,
+    max_length: Option<usize>,
+    check_constraints: bool,
+    #[cfg(feature = "idna")]
+    pub(crate) idna: bool,
+    escape_sets: [Option<EscapeSet>; 8],
+}
+
+impl Default for UriTemplateExpansionOptions {
+    fn default() -> UriTemplateExpansionOptions {
+        UriTemplateExpansionOptions::new()
+    }
+}
+
+impl UriTemplateExpansionOptions {
+    pub fn new() -> UriTemplateExpansionOptions {
+        UriTemplateExpansionOptions {
+            query_aware: false,
+            form_encoded: false,
+            lowercase_hex: false,
+            iri: false,
+            dot_segments: UriTemplateDotSegments::Allow,
+            untrusted: UriTemplateUntrustedPolicy::Restrict(EscapeSet::unreserved()),
+            max_list_length: None,
+            max_length: None,
+            check_constraints: false,
+            #[cfg(feature = "idna")]
+            idna: false,
+            escape_sets: [None; 8],
+        }
+    }
+
+    /// When query aware, a `{?...}` expression expanded after the URI's
+    /// query has started, by an earlier `?` in a literal or in an earlier
+    /// expansion, starts with `&` instead of `?`. Likewise a `{&...}`
+    /// expression expanded before any query has started starts with `?`.
+    /// Expressions in the fragment are left alone.
+    ///
+    /// This is not part of RFC 6570, which expands each expression on its
+    /// own.
+    pub fn query_aware(mut self, query_aware: bool) -> UriTemplateExpansionOptions {
+        self.query_aware = query_aware;
+        self
+    }
+
+    /// When form encoded, values in `{?...}` and `{&...}` expressions are
+    /// encoded as for `application/x-www-form-urlencoded` (per the WHATWG
+    /// URL standard) rather than as RFC 6570 specifies: spaces become `+`
+    /// and everything but ASCII alphanumerics and `*-._` is pct-encoded, so
+    /// `~` becomes `%7E`.
+    ///
+    /// This is not part of RFC 6570, and is meant for servers that decode
+    /// the query as a form and so would read a literal `+` as a space.
+    pub fn form_encoded(mut self, form_encoded: bool) -> UriTemplateExpansionOptions {
+        self.form_encoded = form_encoded;
+        self
+    }
+
+    /// Overrides which characters of values are left unescaped in
+    /// expressions with the given operator (`None` for simple string
+    /// expansion). This takes precedence over `form_encoded`.
+    ///
+    /// Sets other than `EscapeSet::unreserved` for the simple, `.`, `/`,
+    /// `;`, `?` and `&` operators, and `EscapeSet::reserved` for `+` and
+    /// `#`, are not part of RFC 6570. Leaving a separator such as `/` or `&`
+    /// unescaped makes the expansion ambiguous.
+    pub fn escape_set(mut self, operator: Option<UriTemplateOperator>, set: EscapeSet) -> UriTemplateExpansionOptions {
+        self.escape_sets[operator_index(operator)] = Some(set);
+        self
+    }
+
+    /// When set, pct-encoding written by the expansion uses lowercase hex
+    /// digits, e.g. `%c3%a9` rather than `%C3%A9`. Pct-encoded triplets
+    /// already in literals or in values expanded with `+` and `#` are left
+    /// as they are.
+    pub fn lowercase_hex(mut self, lowercase_hex: bool) -> UriTemplateExpansionOptions {
+        self.lowercase_hex = lowercase_hex;
+        self
+    }
+
+    /// When set, the expansion is an IRI (RFC 3987) rather than a URI:
+    /// non-ASCII characters allowed in IRIs (`ucschar`, and in the query
+    /// also `iprivate`) are left unescaped, in literals as well as in
+    /// values, rather than pct-encoded. Use `iri_to_uri` to get the URI
+    /// such an IRI maps to.
+    pub fn iri(mut self, iri: bool) -> UriTemplateExpansionOptions {
+        self.iri = iri;
+        self
+    }
+
+    /// Guards the path against values that would escape the intended
+    /// prefix: in simple, `+` and `/` expressions in the path (before any
+    /// query or fragment, after any authority), values or list items that
+    /// are `.` or `..` (also pct-encoded, for `+`), and `/` in values of
+    /// `+` expressions. Prefix modifiers are applied first.
+    ///
+    /// An expression right after a complete authority, as in
+    /// `https://example.com{/segments*}`, starts the path. A `+` expression
+    /// that may still expand to the scheme or authority, such as `{+base}`
+    /// at the start of the template, is not checked.
+    ///
+    /// There is no option to pct-encode the dots instead, since WHATWG URL
+    /// parsers (browsers, the `url` crate) treat `%2E` and `%2E%2E`
+    /// segments as dot segments too.
+    ///
+    /// `{.x}` and `{;x}` expressions are not checked, as their prefix joins
+    /// the value to the text before it.
+    pub fn dot_segments(mut self, dot_segments: UriTemplateDotSegments) -> UriTemplateExpansionOptions {
+        self.dot_segments = dot_segments;
+        self
+    }
+
+    /// Sets how values marked untrusted (see `UriTemplateVars::is_untrusted`)
+    /// are expanded in expressions whose escape set leaves a `:/?#[]@`
+    /// delimiter or pct-encoded triplets unescaped: by default `+` and `#`
+    /// expressions. Left as they are, such values could add an authority
+    /// (`@evil.com`), a query or a fragment to the URI.
+    ///
+    /// The default is `Restrict(EscapeSet::unreserved())`, which escapes
+    /// them as in a simple `{...}` expression; other values are not
+    /// affected.
+    pub fn untrusted(mut self, untrusted: UriTemplateUntrustedPolicy) -> UriTemplateExpansionOptions {
+        self.untrusted = untrusted;
+        self
+    }
+
+    /// The maximum number of items in a list, or of pairs in an
+    /// associative array, that may be expanded.
+    pub fn max_list_length(mut self, max_list_length: usize) -> UriTemplateExpansionOptions {
+        self.max_list_length = Some(max_list_length);
+        self
+    }
+
+    /// The maximum length of the expansion in bytes. Expansion stops with
+    /// `OutputTooLong` at the first literal, value or list item that would
+    /// exceed it, without writing it.
+    pub fn max_length(mut self, max_length: usize) -> UriTemplateExpansionOptions {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    /// When set, the values of constrained variables, or their default
+    /// values when undefined, are checked as `UriTemplate::check_constraints`
+    /// does before expanding, failing with `ConstraintViolation`. `Custom`
+    /// constraints are not checked, as there is no predicate for them.
+    pub fn check_constraints(mut self, check_constraints: bool) -> UriTemplateExpansionOptions {
+        self.check_constraints = check_constraints;
+        self
+    }
+
+    /// When set, values of `{...}` and `{+...}` expressions in the host of
+    /// the authority, as in `https://{host}/` or `https://{sub}.example.com/`,
+    /// are converted with IDNA ToASCII (UTS #46, as in the WHATWG URL
+    /// standard) instead of being pct-encoded, so that `bücher.example`
+    /// expands to `xn--bcher-kva.example`. This also lowercases ASCII
+    /// letters. Values that are not valid host names are an
+    /// `InvalidHost` error.
+    ///
+    /// An expression is taken to be in the host if the expansion so far is
+    /// `scheme://` or `//` followed by anything but `/`, `?`, `#` or `:`,
+    /// and no `@` follows in the template's literals before the next `/`,
+    /// `?` or `#` (in which case it is userinfo instead). IP literals in
+    /// `[...]` are left alone.
+    #[cfg(feature = "idna")]
+    pub fn idna(mut self, idna: bool) -> UriTemplateExpansionOptions {
+        self.idna = idna;
+        self
+    }
+
+    pub(crate) fn escape_set_for(&self, operator: Option<UriTemplateOperator>) -> EscapeSet {
+        match self.escape_sets[operator_index(operator)] {
+            Some(set) => set,
+            None => match operator {
+                Some(UriTemplateOperator::QueryParameter) | Some(UriTemplateOperator::QueryContinuation) if self.form_encoded => EscapeSet::form_urlencoded(),
+                _ => escaping::default_escape_set(operator),
+            },
+        }
+    }
+}
+
+
+fn operator_index(operator: Option<UriTemplateOperator>) -> usize {
+    match operator {
+        None => 0,
+        Some(UriTemplateOperator::ReservedCharacter) => 1,
+        Some(UriTemplateOperator::Fragment) => 2,
+        Some(UriTemplateOperator::PathExtension) => 3,
+        Some(UriTemplateOperator::PathComponent) => 4,
+        Some(UriTemplateOperator::PathParameter) => 5,
+        Some(UriTemplateOperator::QueryParameter) => 6,
+        Some(UriTemplateOperator::QueryContinuation) => 7,
+    }
+}
+
+
+#[derive(Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub enum UriTemplateExpansionError {
+    /// The value of the named variable, expanded in the host with
+    /// `UriTemplateExpansionOptions::idna`, is not a valid host name.
+    InvalidHost(String),
+    /// The value of the named variable would add a `.` or `..` segment to
+    /// the path, see `UriTemplateExpansionOptions::dot_segments`.
+    DotSegment(String),
+    /// The value of the named variable would add a `/` to the path.
+    PathSeparator(String),
+    /// The value of the named variable is untrusted, and may not be used in
+    /// the expression, see `UriTemplateExpansionOptions::untrusted`.
+    UntrustedValue(String),
+    /// The value of the named variable has more items than
+    /// `UriTemplateExpansionOptions::max_list_length` allows.
+    ListTooLong(String),
+    /// The expansion is longer than `UriTemplateExpansionOptions::max_length`
+    /// allows.
+    OutputTooLong(usize),
+    /// The value of the named variable does not satisfy its constraint, see
+    /// `UriTemplate::check_constraints` and
+    /// `UriTemplateExpansionOptions::check_constraints`.
+    ConstraintViolation(String),
+}
+
+impl fmt::Display for UriTemplateExpansionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UriTemplateExpansionError::InvalidHost(ref name) => write!(f, "value of {:?} is not a valid host name", name),
+            UriTemplateExpansionError::DotSegment(ref name) => write!(f, "value of {:?} is a dot segment", name),
+            UriTemplateExpansionError::PathSeparator(ref name) => write!(f, "value of {:?} contains a path separator", name),
+            UriTemplateExpansionError::UntrustedValue(ref name) => write!(f, "untrusted value of {:?} used in a reserved expansion", name),
+            UriTemplateExpansionError::ListTooLong(ref name) => write!(f, "value of {:?} has too many items", name),
+            UriTemplateExpansionError::OutputTooLong(max_length) => write!(f, "expansion exceeds {} bytes", max_length),
+            UriTemplateExpansionError::ConstraintViolation(ref name) => write!(f, "value of {:?} does not satisfy its constraint", name),
+        }
+    }
+}
+
+impl Error for UriTemplateExpansionError {}
+
+
+#[cfg(feature = "idna")]
+pub(crate) fn host_to_ascii(name: &str, value: &UriTemplateValue) -> Result<UriTemplateValue, UriTemplateExpansionError> {
+    let invalid = || UriTemplateExpansionError::InvalidHost(name.to_string());
+    match *value {
+        UriTemplateValue::String(ref host) => {
+            let host = idna::domain_to_ascii_cow(host.as_bytes(), idna::AsciiDenyList::URL).map_err(|_| invalid())?;
+            Ok(UriTemplateValue::String(host.into_owned()))
+        },
+        _ => Err(invalid()),
+    }
+}
+
+// Whether `item` is `.` or `..`, with the dots pct-encoded if `reserved`
+// expansion would leave them so.
+fn is_dot_segment(item: &str, reserved: bool) -> bool {
+    let mut rest = item;
+    let mut dots = 0;
+    while !rest.is_empty() {
+        rest = match rest.strip_prefix('.') {
+            Some(rest) => rest,
+            None if reserved && (rest.starts_with("%2E") || rest.starts_with("%2e")) => &rest[3..],
+            None => return false,
+        };
+        dots += 1;
+    }
+    dots == 1 || dots == 2
+}
+
+// Rejects an item of the value of `name` that would add a dot segment or,
+// for `reserved` expansion, a `/` to the path.
+pub(crate) fn check_path_item(name: &str, item: &str, reserved: bool) -> Result<(), UriTemplateExpansionError> {
+    if is_dot_segment(item, reserved) {
+        return Err(UriTemplateExpansionError::DotSegment(name.to_string()));
+    }
+    if reserved && item.contains('/') {
+        return Err(UriTemplateExpansionError::PathSeparator(name.to_string()));
+    }
+    Ok(())
+}
+
+fn is_scheme(scheme: &str) -> bool {
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic()) &&
+        scheme.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+}
+
+// The part of `head` after the `//` that starts its authority, if any.
+fn authority(head: &str) -> Option<&str> {
+    if let Some(rest) = head.strip_prefix("//") {
+        return Some(rest);
+    }
+    let colon = head.find(':')?;
+    if !is_scheme(&head[..colon]) {
+        return None;
+    }
+    head[colon + 1..].strip_prefix("//")
+}
+
+// Whether `authority` has a host that more text would not continue, so
+// that the path may start after it.
+fn is_complete_authority(authority: &str) -> bool {
+    !authority.is_empty() && !authority.ends_with(&['.', ':', '@', '-'][..]) &&
+        (authority.contains(']') || !authority.contains('['))
+}
+
+// Whether more text appended to `head` could still give it an authority.
+fn may_get_authority(head: &str) -> bool {
+    let after_scheme = match head.find(':') {
+        Some(colon) if is_scheme(&head[..colon]) => &head[colon + 1..],
+        Some(_) => return false,
+        None if head.is_empty() || is_scheme(head) => return true,
+        None => head,
+    };
+    "//".starts_with(after_scheme)
+}
+
+// Whether a literal `@` follows in the template before the end of the
+// authority, making an expression at its start userinfo rather than host.
+fn userinfo_follows(components: &[UriTemplateComponent]) -> bool {
+    for component in components.iter() {
+        if let UriTemplateComponent::Literal(ref value) = *component {
+            if let Some(position) = value.find(&['/', '?', '#', '@'][..]) {
+                return value.as_bytes()[position] == b'@';
+            }
+        }
+    }
+    false
+}
+
+
+/// The part of an expansion produced by a component of the template, as
+/// returned by `UriTemplate::expand_with_spans`.
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub struct UriTemplateSpan {
+    start: usize,
+    end: usize,
+    component: usize,
+    variable: Option<usize>,
+    undefined: bool,
+}
+
+impl UriTemplateSpan {
+    /// The byte range of the expansion.
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// The index of the component in `UriTemplate::components`.
+    pub fn component(&self) -> usize {
+        self.component
+    }
+
+    /// The index of the variable in the expression, or `None` for a
+    /// literal.
+    pub fn variable(&self) -> Option<usize> {
+        self.variable
+    }
+
+    /// Whether the variable was skipped as undefined (RFC 6570 section
+    /// 2.3), which includes empty lists and associative arrays. The range
+    /// of an undefined variable is empty.
+    pub fn is_undefined(&self) -> bool {
+        self.undefined
+    }
+}
+
+
+// What the URI expanded so far contains, as far as the expansion of later
+// components depends on it.
+#[derive(Default)]
+pub(crate) struct ExpansionState {
+    query: bool,
+    fragment: bool,
+    // The expansion so far, for as long as it may be in the authority.
+    head: String,
+    past_authority: bool,
+    userinfo_follows: bool,
+    // Whether no option depends on the state, so that it need not be kept.
+    untracked: bool,
+    // The spans of the expansion, when they are being recorded.
+    spans: Option<Vec<UriTemplateSpan>>,
+}
+
+impl ExpansionState {
+    pub(crate) fn new(options: &UriTemplateExpansionOptions) -> ExpansionState {
+        #[cfg(feature = "idna")]
+        let idna = options.idna;
+        #[cfg(not(feature = "idna"))]
+        let idna = false;
+        ExpansionState {
+            untracked: !options.query_aware && !options.iri && options.dot_segments == UriTemplateDotSegments::Allow && !idna,
+            ..ExpansionState::default()
+        }
+    }
+
+    pub(crate) fn in_query(&self) -> bool {
+        self.query && !self.fragment
+    }
+
+    pub(crate) fn in_fragment(&self) -> bool {
+        self.fragment
+    }
+
+    // Whether an expression with `operator` expanded next is in the path.
+    // One that follows a complete authority starts the path, unless it is a
+    // simple expression, which would add to the host or port; a `+`
+    // expression that can still produce the scheme or authority is not in
+    // the path.
+    pub(crate) fn in_path(&self, operator: Option<UriTemplateOperator>) -> bool {
+        if self.query || self.fragment {
+            return false;
+        }
+        if self.past_authority {
+            return true;
+        }
+        match authority(&self.head) {
+            Some(authority) => operator.is_some() && is_complete_authority(authority),
+            None => operator != Some(UriTemplateOperator::ReservedCharacter) || !may_get_authority(&self.head),
+        }
+    }
+
+    #[cfg_attr(not(feature = "idna"), allow(dead_code))]
+    pub(crate) fn in_host(&self) -> bool {
+        if self.past_authority || self.userinfo_follows {
+            return false;
+        }
+        match authority(&self.head) {
+            Some(authority) => {
+                let host = authority.rsplit('@').next().unwrap_or("");
+                !host.starts_with('[') && !host.contains(':')
+            },
+            None => false,
+        }
+    }
+
+    // Takes the next piece of the expansion into account.
+    pub(crate) fn visit(&mut self, expanded: &str) {
+        if self.untracked {
+            return;
+        }
+        if !self.past_authority {
+            self.head.push_str(expanded);
+            self.past_authority = match authority(&self.head) {
+                Some(authority) => authority.contains(&['/', '?', '#'][..]),
+                None => !may_get_authority(&self.head),
+            };
+            if self.past_authority {
+                self.head = String::new();
+            }
+        }
+        if self.fragment {
+            return;
+        }
+        let before_fragment = match expanded.find('#') {
+            Some(position) => {
+                self.fragment = true;
+                &expanded[..position]
+            },
+            None => expanded,
+        };
+        if before_fragment.contains('?') {
+            self.query = true;
+        }
+    }
+
+    // Writes the expansion of `components` to `out`.
+    pub(crate) fn expand_components<V: UriTemplateVars + ?Sized, S: Sink>(&mut self, components: &[UriTemplateComponent], values: &V, options: &UriTemplateExpansionOptions, out: &mut S) -> Result<(), UriTemplateExpansionError> {
+        if options.check_constraints {
+            constraints::check_components(components, values, |_, _| true)?;
+        }
+        let mut writer = ExpansionWriter { state: self, out, len: 0, max_length: options.max_length, overflowed: false, component: 0 };
+        for (i, component) in components.iter().enumerate() {
+            writer.component = i;
+            if !writer.state.untracked {
+                writer.state.userinfo_follows = userinfo_follows(&components[i + 1..]);
+            }
+            component.expand(values, options, &mut writer)?;
+            writer.check_length()?;
+        }
+        Ok(())
+    }
+}
+
+
+// Writes the expansion to `out`, keeping `state` up to date with it as it
+// is written.
+pub(crate) struct ExpansionWriter<'a, S: Sink + 'a> {
+    pub(crate) state: &'a mut ExpansionState,
+    out: &'a mut S,
+    pub(crate) len: usize,
+    max_length: Option<usize>,
+    // Whether output was dropped for exceeding `max_length`.
+    overflowed: bool,
+    // The index of the component being expanded.
+    component: usize,
+}
+
+impl<'a, S: Sink> ExpansionWriter<'a, S> {
+    // Records that the output since `start` was produced by the component
+    // being expanded, or by its `variable`.
+    // Fails with `OutputTooLong` if output was dropped for exceeding
+    // `max_length`.
+    pub(crate) fn check_length(&self) -> Result<(), UriTemplateExpansionError> {
+        match self.max_length {
+            Some(max_length) if self.overflowed => Err(UriTemplateExpansionError::OutputTooLong(max_length)),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn span(&mut self, start: usize, variable: Option<usize>, undefined: bool) {
+        if let Some(ref mut spans) = self.state.spans {
+            spans.push(UriTemplateSpan {
+                start,
+                end: self.len,
+                component: self.component,
+                variable,
+                undefined,
+            });
+        }
+    }
+}
+
+impl<'a, S: Sink> Sink for ExpansionWriter<'a, S> {
+    // Output past `max_length` is dropped rather than written, until
+    // `check_length` stops the expansion.
+    fn push_str(&mut self, s: &str) {
+        if self.overflowed || self.max_length.map(|max_length| self.len + s.len() > max_length).unwrap_or(false) {
+            self.overflowed = true;
+            return;
+        }
+        self.state.visit(s);
+        self.len += s.len();
+        self.out.push_str(s);
+    }
+}
+
+
+// Counts the bytes of the expansion instead of keeping it.
+struct ByteCount(usize);
+
+impl Sink for ByteCount {
+    fn push_str(&mut self, s: &str) {
+        self.0 += s.len();
+    }
+}
+
+
+impl UriTemplate {
+    /// Expands the template as `to_string_with_values` does, with the
+    /// extensions to RFC 6570 enabled in `options`.
+    pub fn to_string_with_options<V: UriTemplateVars + ?Sized>(&self, values: &V, options: &UriTemplateExpansionOptions) -> Result<String, UriTemplateExpansionError> {
+        let mut expanded = String::new();
+        ExpansionState::new(options).expand_components(self.components(), values, options, &mut expanded)?;
+        Ok(expanded)
+    }
+
+    /// The length in bytes of `to_string_with_values(values)`, computed by
+    /// the same expansion without building the string.
+    pub fn expanded_len<V: UriTemplateVars + ?Sized>(&self, values: &V) -> usize {
+        let options = UriTemplateExpansionOptions::new();
+        let mut len = ByteCount(0);
+        match ExpansionState::new(&options).expand_components(self.components(), values, &options, &mut len) {
+            Ok(()) => len.0,
+            Err(_) => unreachable!("expansion with the default options cannot fail"),
+        }
+    }
+
+    /// A bound on `expanded_len(values)` that does not look at the contents
+    /// of the values, only at their lengths, so it takes time proportional
+    /// to the number of values rather than to the size of the expansion.
+    pub fn expanded_len_upper_bound<V: UriTemplateVars + ?Sized>(&self, values: &V) -> usize {
+        self.components().iter().map(|component| component.expanded_len_upper_bound(values)).sum()
+    }
+
+    /// Expands the template as `to_string_with_values` does, also returning
+    /// which component produced each part of the expansion, in order. Each
+    /// literal has a span, and so has each variable of an expression,
+    /// including the prefix or separator written before it, so that the
+    /// spans cover the expansion. Undefined variables have an empty span
+    /// where they would have been expanded.
+    pub fn expand_with_spans<V: UriTemplateVars + ?Sized>(&self, values: &V) -> (String, Vec<UriTemplateSpan>) {
+        let options = UriTemplateExpansionOptions::new();
+        let mut state = ExpansionState::new(&options);
+        state.spans = Some(vec!());
+        let mut expanded = String::new();
+        if state.expand_components(self.components(), values, &options, &mut expanded).is_err() {
+            unreachable!("expansion with the default options cannot fail");
+        }
+        (expanded, state.spans.unwrap_or_default())
+    }
+
+    /// Expands the template query aware (see
+    /// `UriTemplateExpansionOptions::query_aware`) as a continuation of the
+    /// already expanded `uri`, so that `{?page}` appended to `/list?a=1`
+    /// gives `/list?a=1&page=2`.
+    ///
+    /// The expansion is inserted before the fragment of `uri`, if any,
+    /// unless the expansion has a fragment of its own, which then replaces
+    /// that of `uri`.
+    pub fn append_to_uri<V: UriTemplateVars + ?Sized>(&self, uri: &str, values: &V) -> String {
+        let (base, fragment) = match uri.find('#') {
+            Some(position) => (&uri[..position], &uri[position..]),
+            None => (uri, ""),
+        };
+        let options = UriTemplateExpansionOptions::new().query_aware(true);
+        let mut state = ExpansionState::new(&options);
+        state.visit(base);
+        let mut expanded = String::from(base);
+        if state.expand_components(self.components(), values, &options, &mut expanded).is_err() {
+            unreachable!("query aware expansion cannot fail");
+        }
+        if !state.in_fragment() {
+            expanded.push_str(fragment);
+        }
+        expanded
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{EscapeSet, UriTemplate, UriTemplateOperator, UriTemplateValues, iri_to_uri};
+    use super::{ExpansionState, UriTemplateDotSegments, UriTemplateExpansionError, UriTemplateExpansionOptions, UriTemplateUntrustedPolicy};
+
+    fn values() -> UriTemplateValues {
+        let mut v = UriTemplateValues::new();
+        v.set("base", "http://example.com/list?a=1");
+        v.set("page", "2");
+        v.set("size", "10");
+        v
+    }
+
+    #[test]
+    fn test_query_aware() {
+        let options = UriTemplateExpansionOptions::new().query_aware(true);
+        let expand = |template: &str| UriTemplate::parse(template).unwrap().to_string_with_options(&values(), &options).unwrap();
+        assert_eq!(expand("/list?a=1{?page,size}"), "/list?a=1&page=2&size=10");
+        assert_eq!(expand("{+base}{?page,size}"), "http://example.com/list?a=1&page=2&size=10");
+        assert_eq!(expand("/list{?page}{?size}"), "/list?page=2&size=10");
+        assert_eq!(expand("/list{&page}"), "/list?page=2");
+        assert_eq!(expand("/list{?missing}{?page}"), "/list?page=2");
+        assert_eq!(expand("/list#a?b{?page}"), "/list#a?b?page=2");
+    }
+
+    #[test]
+    fn test_default_is_rfc_6570() {
+        let t = UriTemplate::parse("/list?a=1{?page}{&size}").unwrap();
+        assert_eq!(t.to_string_with_options(&values(), &UriTemplateExpansionOptions::new()).unwrap(), "/list?a=1?page=2&size=10");
+        assert_eq!(t.to_string_with_options(&values(), &UriTemplateExpansionOptions::new()).unwrap(), t.to_string_with_values(&values()));
+    }
+
+    #[test]
+    fn test_form_encoded() {
+        let mut v = UriTemplateValues::new();
+        v.set("q", "fish & chips~+1");
+        v.set("tags", ["a b", "c*d"].as_ref());
+        v.set("keys", [("first name", "J R"), ("x", "~")].as_ref());
+        let rfc = UriTemplateExpansionOptions::new();
+        let form = UriTemplateExpansionOptions::new().form_encoded(true);
+        let expand = |template: &str, options: &UriTemplateExpansionOptions| UriTemplate::parse(template).unwrap().to_string_with_options(&v, options).unwrap();
+
+        assert_eq!(expand("{?q}", &rfc), "?q=fish%20%26%20chips~%2B1");
+        assert_eq!(expand("{?q}", &form), "?q=fish+%26+chips%7E%2B1");
+        assert_eq!(expand("{&tags*}", &rfc), "&tags=a%20b&tags=c%2Ad");
+        assert_eq!(expand("{&tags*}", &form), "&tags=a+b&tags=c*d");
+        assert_eq!(expand("{?keys*}", &form), "?first+name=J+R&x=%7E");
+
+        // Other operators are unaffected.
+        assert_eq!(expand("/{q}{/tags}", &form), expand("/{q}{/tags}", &rfc));
+    }
+
+    #[test]
+    fn test_escape_set() {
+        let mut v = UriTemplateValues::new();
+        v.set("key", "user@example.com:8080/x");
+        v.set("token", "a~b c");
+        let expand = |template: &str, options: &UriTemplateExpansionOptions| UriTemplate::parse(template).unwrap().to_string_with_options(&v, options).unwrap();
+
+        let storage = UriTemplateExpansionOptions::new()
+            .escape_set(Some(UriTemplateOperator::PathComponent), EscapeSet::unreserved().with_chars(":@"));
+        assert_eq!(expand("/bucket{/key}", &storage), "/bucket/user@example.com:8080%2Fx");
+        assert_eq!(expand("/bucket/{key}", &storage), "/bucket/user%40example.com%3A8080%2Fx");
+
+        let signing = UriTemplateExpansionOptions::new()
+            .escape_set(None, EscapeSet::unreserved().without_chars("~"))
+            .escape_set(Some(UriTemplateOperator::QueryParameter), EscapeSet::from_predicate(|b| b.is_ascii_alphanumeric()));
+        assert_eq!(expand("{token}{?token}", &signing), "a%7Eb%20c?token=a%7Eb%20c");
+
+        let form = UriTemplateExpansionOptions::new().form_encoded(true).escape_set(Some(UriTemplateOperator::QueryContinuation), EscapeSet::unreserved());
+        assert_eq!(expand("{?token}{&token}", &form), "?token=a%7Eb+c&token=a~b%20c");
+    }
+
+    #[test]
+    fn test_lowercase_hex() {
+        let mut v = UriTemplateValues::new();
+        v.set("x", "café/%2F");
+        let t = UriTemplate::parse("/%C3%A9{/x}{+x}").unwrap();
+        let options = UriTemplateExpansionOptions::new().lowercase_hex(true);
+        assert_eq!(t.to_string_with_options(&v, &options).unwrap(), "/%C3%A9/caf%c3%a9%2f%252Fcaf%c3%a9/%2F");
+    }
+
+    #[test]
+    fn test_iri() {
+        let mut v = UriTemplateValues::new();
+        v.set("name", "crème brûlée");
+        v.set("private", "\u{E000}");
+        let t = UriTemplate::parse("https://example.com/café/{name}{?name,private}{#private}").unwrap();
+        let iri = t.to_string_with_options(&v, &UriTemplateExpansionOptions::new().iri(true)).unwrap();
+        assert_eq!(iri, "https://example.com/café/crème%20brûlée?name=crème%20brûlée&private=\u{E000}#%EE%80%80");
+        assert_eq!(iri_to_uri(&iri), t.to_string_with_values(&v));
+
+        let t = UriTemplate::parse("/\u{E000}?\u{E000}#\u{E000}{/private}").unwrap();
+        assert_eq!(t.to_string_with_options(&v, &UriTemplateExpansionOptions::new().iri(true)).unwrap(), "/%EE%80%80?\u{E000}#%EE%80%80/%EE%80%80");
+    }
+
+    #[test]
+    fn test_dot_segments() {
+        let mut v = UriTemplateValues::new();
+        v.set("dot", ".");
+        v.set("dots", "..");
+        v.set("hidden", ".profile");
+        v.set("path", "../../admin");
+        v.set("encoded", "%2e%2E");
+        v.set("segments", ["docs", ".."].as_ref());
+        let reject = UriTemplateExpansionOptions::new().dot_segments(UriTemplateDotSegments::Reject);
+        let expand = |template: &str, options: &UriTemplateExpansionOptions| UriTemplate::parse(template).unwrap().to_string_with_options(&v, options);
+
+        assert_eq!(expand("/files/{dots}", &UriTemplateExpansionOptions::new()), Ok("/files/..".to_string()));
+        assert_eq!(expand("/files/{dots}", &reject), Err(UriTemplateExpansionError::DotSegment("dots".to_string())));
+        assert_eq!(expand("/files{/dot}", &reject), Err(UriTemplateExpansionError::DotSegment("dot".to_string())));
+        assert_eq!(expand("/files{/segments*}", &reject), Err(UriTemplateExpansionError::DotSegment("segments".to_string())));
+        assert_eq!(expand("/files/{+encoded}", &reject), Err(UriTemplateExpansionError::DotSegment("encoded".to_string())));
+        assert_eq!(expand("/files/{+path}", &reject), Err(UriTemplateExpansionError::PathSeparator("path".to_string())));
+        assert_eq!(expand("/files/{hidden}{/encoded}{?dots}{#path}", &reject), Ok("/files/.profile/%252e%252E?dots=..#../../admin".to_string()));
+        assert_eq!(expand("/files/{path}", &reject), Ok("/files/..%2F..%2Fadmin".to_string()));
+        assert_eq!(expand("/files/{hidden:1}", &reject), Err(UriTemplateExpansionError::DotSegment("hidden".to_string())));
+        assert_eq!(expand("//{dots}/", &reject), Ok("//../".to_string()));
+        assert_eq!(expand("https://example.com{/segments*}", &reject), Err(UriTemplateExpansionError::DotSegment("segments".to_string())));
+        assert_eq!(expand("https://example.com{+path}", &reject), Err(UriTemplateExpansionError::PathSeparator("path".to_string())));
+        assert_eq!(expand("https://example.{dots}/", &reject), Ok("https://example.../".to_string()));
+    }
+
+    #[test]
+    fn test_dot_segments_after_authority() {
+        let mut v = UriTemplateValues::new();
+        v.set("base", "https://example.com");
+        v.set("segments", ["docs", "..", "admin"].as_ref());
+        v.set("safe", ["docs", "intro"].as_ref());
+        let reject = UriTemplateExpansionOptions::new().dot_segments(UriTemplateDotSegments::Reject);
+        let expand = |template: &str| UriTemplate::parse(template).unwrap().to_string_with_options(&v, &reject);
+
+        assert_eq!(expand("{+base}{/safe*}"), Ok("https://example.com/docs/intro".to_string()));
+        assert_eq!(expand("{+base}{/segments*}"), Err(UriTemplateExpansionError::DotSegment("segments".to_string())));
+        assert_eq!(expand("{+base}/files{/segments*}"), Err(UriTemplateExpansionError::DotSegment("segments".to_string())));
+    }
+
+    #[test]
+    fn test_untrusted() {
+        let mut v = UriTemplateValues::new();
+        v.set("base", "https://api.example.com/v1");
+        v.set_untrusted("redirect", "@evil.com/x?y#z");
+        let t = UriTemplate::parse("{+base}/login{?redirect}{#redirect}").unwrap();
+        let expand = |options: &UriTemplateExpansionOptions| t.to_string_with_options(&v, options);
+
+        let escaped = "https://api.example.com/v1/login?redirect=%40evil.com%2Fx%3Fy%23z#%40evil.com%2Fx%3Fy%23z";
+        assert_eq!(expand(&UriTemplateExpansionOptions::new()), Ok(escaped.to_string()));
+        assert_eq!(t.to_string_with_values(&v), escaped);
+        assert_eq!(expand(&UriTemplateExpansionOptions::new().untrusted(UriTemplateUntrustedPolicy::Allow)),
+            Ok("https://api.example.com/v1/login?redirect=%40evil.com%2Fx%3Fy%23z#@evil.com/x?y#z".to_string()));
+        assert_eq!(expand(&UriTemplateExpansionOptions::new().untrusted(UriTemplateUntrustedPolicy::Reject)),
+            Err(UriTemplateExpansionError::UntrustedValue("redirect".to_string())));
+        let restricted = UriTemplateUntrustedPolicy::Restrict(EscapeSet::reserved().without_chars("@?#").allow_pct_encoded(false));
+        assert_eq!(expand(&UriTemplateExpansionOptions::new().untrusted(restricted)),
+            Ok("https://api.example.com/v1/login?redirect=%40evil.com%2Fx%3Fy%23z#%40evil.com/x%3Fy%23z".to_string()));
+
+        // Custom escape sets that leave delimiters unescaped are guarded too.
+        let options = UriTemplateExpansionOptions::new()
+            .escape_set(Some(UriTemplateOperator::QueryParameter), EscapeSet::unreserved().with_chars("/@"))
+            .untrusted(UriTemplateUntrustedPolicy::Reject);
+        assert_eq!(expand(&options), Err(UriTemplateExpansionError::UntrustedValue("redirect".to_string())));
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut v = UriTemplateValues::new();
+        v.set("list", ["a", "b", "c"].as_ref());
+        v.set("keys", [("a", "1"), ("b", "2")].as_ref());
+        v.set("long", "0123456789");
+        let options = UriTemplateExpansionOptions::new().max_list_length(2).max_length(16);
+        let expand = |template: &str| UriTemplate::parse(template).unwrap().to_string_with_options(&v, &options);
+        assert_eq!(expand("/{keys*}/{long:5}"), Ok("/a=1,b=2/01234".to_string()));
+        assert_eq!(expand("/{list}"), Err(UriTemplateExpansionError::ListTooLong("list".to_string())));
+        assert_eq!(expand("/{long}{long}"), Err(UriTemplateExpansionError::OutputTooLong(16)));
+        assert_eq!(expand("/aaaaaaaaaaaaaaaa"), Err(UriTemplateExpansionError::OutputTooLong(16)));
+    }
+
+    #[test]
+    fn test_max_length_before_writing() {
+        let mut v = UriTemplateValues::new();
+        v.set("huge", "x".repeat(1 << 20));
+        v.set("list", vec!("y".repeat(1 << 20); 2));
+        let options = UriTemplateExpansionOptions::new().max_length(16);
+        for &(template, written) in [("/{huge}", "/"), ("/{list}", "/"), ("/{?list*}", "/?list=")].iter() {
+            let t = UriTemplate::parse(template).unwrap();
+            let mut expanded = String::new();
+            let result = ExpansionState::new(&options).expand_components(t.components(), &v, &options, &mut expanded);
+            assert_eq!(result, Err(UriTemplateExpansionError::OutputTooLong(16)));
+            assert_eq!(expanded, written);
+        }
+    }
+
+    #[test]
+    fn test_host_position() {
+        let in_host = |head: &[&str]| {
+            let mut state = ExpansionState::default();
+            for expanded in head.iter() {
+                state.visit(expanded);
+            }
+            state.in_host()
+        };
+        assert!(in_host(&["https://"]));
+        assert!(in_host(&["https", "://"]));
+        assert!(in_host(&["//"]));
+        assert!(in_host(&["https://user@"]));
+        assert!(in_host(&["https://", "sub."]));
+        assert!(!in_host(&[""]));
+        assert!(!in_host(&["https:"]));
+        assert!(!in_host(&["https://example.com:"]));
+        assert!(!in_host(&["https://["]));
+        assert!(!in_host(&["https://example.com/"]));
+        assert!(!in_host(&["https://example.com/", "//"]));
+        assert!(!in_host(&["/path//"]));
+        assert!(!in_host(&["mailto:"]));
+    }
+
+    #[cfg(feature = "idna")]
+    #[test]
+    fn test_idna() {
+        let mut v = UriTemplateValues::new();
+        v.set("host", "Bücher.example");
+        v.set("sub", "bücher");
+        v.set("port", "8080");
+        v.set("bad", "exa mple.com");
+        v.set("list", ["a", "b"].as_ref());
+        let options = UriTemplateExpansionOptions::new().idna(true);
+        let expand = |template: &str| UriTemplate::parse(template).unwrap().to_string_with_options(&v, &options);
+
+        assert_eq!(expand("https://{host}/{sub}"), Ok("https://xn--bcher-kva.example/b%C3%BCcher".to_string()));
+        assert_eq!(expand("https://{sub}.example.com:{port}/"), Ok("https://xn--bcher-kva.example.com:8080/".to_string()));
+        assert_eq!(expand("{+host}"), Ok("B%C3%BCcher.example".to_string()));
+        assert_eq!(expand("//{sub}@{host}{?sub}"), Ok("//b%C3%BCcher@xn--bcher-kva.example?sub=b%C3%BCcher".to_string()));
+        assert_eq!(expand("https://{bad}/"), Err(UriTemplateExpansionError::InvalidHost("bad".to_string())));
+        assert_eq!(expand("https://{list}/"), Err(UriTemplateExpansionError::InvalidHost("list".to_string())));
+        assert_eq!(UriTemplate::parse("https://{host}/").unwrap().to_string_with_values(&v), "https://B%C3%BCcher.example/");
+    }
+
+    #[test]
+    fn test_append_to_uri() {
+        let t = UriTemplate::parse("{?page,size}").unwrap();
+        assert_eq!(t.append_to_uri("http://example.com/list", &values()), "http://example.com/list?page=2&size=10");
+        assert_eq!(t.append_to_uri("http://example.com/list?a=1", &values()), "http://example.com/list?a=1&page=2&size=10");
+        assert_eq!(t.append_to_uri("http://example.com/list?a=1#top", &values()), "http://example.com/list?a=1&page=2&size=10#top");
+        assert_eq!(t.append_to_uri("/list", &UriTemplateValues::new()), "/list");
+
+        let t = UriTemplate::parse("{#page}").unwrap();
+        assert_eq!(t.append_to_uri("/list#top", &values()), "/list#2");
+    }
+
+    #[test]
+    fn test_expanded_len() {
+        let mut v = UriTemplateValues::new();
+        v.set("var", "value");
+        v.set("hello", "Hello World!");
+        v.set("empty", "");
+        v.set("path", "/foo/bar");
+        v.set("cafe", "café");
+        v.set("list", ["red", "green", "blue"].as_ref());
+        v.set("keys", [("semi", ";"), ("dot", "."), ("comma", ",")].as_ref());
+        v.set("none", [""; 0].as_ref());
+        let templates = [
+            "", "/static/path?a=b#c", "{var}", "{hello}", "{cafe:3}", "{+path}/here", "{#path,hello}",
+            "{.list*}", "{/list,keys}", "{;empty,list*}", "{;keys*}", "{?var,empty,none,missing}", "{&keys*}",
+            "X{.var:3}", "{?list*,keys}", "{/var:1,var}",
+        ];
+        for template in templates.iter() {
+            let t = UriTemplate::parse(template).unwrap();
+            let expanded = t.to_string_with_values(&v);
+            assert_eq!(t.expanded_len(&v), expanded.len(), "{}", template);
+            assert!(t.expanded_len_upper_bound(&v) >= expanded.len(), "{}", template);
+        }
+        assert_eq!(UriTemplate::parse("{missing}").unwrap().expanded_len_upper_bound(&v), 0);
+    }
+
+    #[test]
+    fn test_expand_with_spans() {
+        let mut v = UriTemplateValues::new();
+        v.set("id", "42");
+        v.set("q", "a b");
+        v.set("none", [""; 0].as_ref());
+        let t = UriTemplate::parse("/users/{id}{?missing,q,none}").unwrap();
+        let (expanded, spans) = t.expand_with_spans(&v);
+        assert_eq!(expanded, "/users/42?q=a%20b");
+        let spans: Vec<_> = spans.iter().map(|s| (&expanded[s.range()], s.component(), s.variable(), s.is_undefined())).collect();
+        assert_eq!(spans, vec!(
+            ("/users/", 0, None, false),
+            ("42", 1, Some(0), false),
+            ("", 2, Some(0), true),
+            ("?q=a%20b", 2, Some(1), false),
+            ("", 2, Some(2), true),
+        ));
+    }
+}
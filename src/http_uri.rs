@@ -0,0 +1,154 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+use http::Uri;
+use http::uri::InvalidUri;
+
+use super::types::{UriTemplate, UriTemplateComponent, UriTemplateExpansion, UriTemplateValues, UriTemplateVars};
+
+
+/// An expansion that did not produce a valid `http::Uri`.
+#[derive(Debug)]
+pub struct UriTemplateUriError {
+    template: String,
+    values: UriTemplateValues,
+    expanded: String,
+    source: InvalidUri,
+}
+
+impl UriTemplateUriError {
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// The values the template was expanded with, of the variables the
+    /// template uses.
+    pub fn values(&self) -> &UriTemplateValues {
+        &self.values
+    }
+
+    pub fn expanded(&self) -> &str {
+        &self.expanded
+    }
+}
+
+impl fmt::Display for UriTemplateUriError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "template {:?} with values {{", self.template)?;
+        for (i, (name, value)) in self.values.iter().enumerate() {
+            write!(f, "{}{}={:?}", if i == 0 { "" } else { ", " }, name, value.to_string())?;
+        }
+        write!(f, "}} expanded to invalid URI {:?}: {}", self.expanded, self.source)
+    }
+}
+
+impl Error for UriTemplateUriError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+
+// An owned copy of the values of the variables `template` uses.
+fn snapshot<V: UriTemplateVars + ?Sized>(template: &UriTemplate, values: &V) -> UriTemplateValues {
+    let mut snapshot = UriTemplateValues::new();
+    for component in template.components().iter() {
+        if let UriTemplateComponent::Variable(_, ref variables) = *component {
+            for v in variables.iter() {
+                if let Some(value) = values.uri_template_value(v.name()) {
+                    if values.is_untrusted(v.name()) {
+                        snapshot.set_untrusted(v.name(), value.into_owned());
+                    } else {
+                        snapshot.set(v.name(), value.into_owned());
+                    }
+                }
+            }
+        }
+    }
+    snapshot
+}
+
+impl UriTemplate {
+    pub fn expand_uri<V: UriTemplateVars + ?Sized>(&self, values: &V) -> Result<Uri, UriTemplateUriError> {
+        Uri::try_from(self.with_values(values))
+    }
+}
+
+impl<'a, V: UriTemplateVars + ?Sized + 'a> TryFrom<UriTemplateExpansion<'a, V>> for Uri {
+    type Error = UriTemplateUriError;
+
+    fn try_from(expansion: UriTemplateExpansion<'a, V>) -> Result<Uri, UriTemplateUriError> {
+        let expanded = expansion.to_string();
+        Uri::try_from(expanded.as_str()).map_err(|source| UriTemplateUriError {
+            template: expansion.template().to_template_string(),
+            values: snapshot(expansion.template(), expansion.values()),
+            expanded,
+            source,
+        })
+    }
+}
+
+/// Expands the template with no variables defined.
+impl<'a> TryFrom<&'a UriTemplate> for Uri {
+    type Error = UriTemplateUriError;
+
+    fn try_from(template: &'a UriTemplate) -> Result<Uri, UriTemplateUriError> {
+        Uri::try_from(template.with_values(&UriTemplateValues::new()))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryFrom;
+
+    use http::Uri;
+
+    use super::super::{UriTemplate, UriTemplateValue, UriTemplateValues, UriTemplateVars};
+
+    #[test]
+    fn test_expand_uri() {
+        let t = UriTemplate::parse("http://example.com/users/{id}{?fields*}").unwrap();
+
+        let mut v = UriTemplateValues::new();
+        v.set("id", "42");
+        v.set("fields", ["name", "email"].as_ref());
+
+        let uri = t.expand_uri(&v).unwrap();
+        assert_eq!(uri.host(), Some("example.com"));
+        assert_eq!(uri.path(), "/users/42");
+        assert_eq!(uri.query(), Some("fields=name&fields=email"));
+    }
+
+    #[test]
+    fn test_try_from() {
+        let t = UriTemplate::parse("/status{?verbose}").unwrap();
+        assert_eq!(Uri::try_from(&t).unwrap(), "/status");
+
+        let mut v = UriTemplateValues::new();
+        v.set("verbose", "1");
+        assert_eq!(Uri::try_from(t.with_values(&v)).unwrap(), "/status?verbose=1");
+    }
+
+    #[test]
+    fn test_invalid_uri() {
+        let t = UriTemplate::parse("http://{+host}/").unwrap();
+
+        let mut v = UriTemplateValues::new();
+        v.set("host", "[::1");
+        v.set("unused", "x");
+
+        let e = t.expand_uri(&v).unwrap_err();
+        assert_eq!(e.template(), "http://{+host}/");
+        assert_eq!(e.values().uri_template_value("host").unwrap().into_owned(), UriTemplateValue::String("[::1".to_string()));
+        assert!(e.values().uri_template_value("unused").is_none());
+        assert_eq!(e.expanded(), "http://[::1/");
+        assert!(e.to_string().starts_with("template \"http://{+host}/\" with values {host=\"[::1\"} expanded to invalid URI \"http://[::1/\": "));
+
+        let t = UriTemplate::parse("http://{+host}/{+path}").unwrap();
+        v.set("path", ["a b", "c"].as_ref());
+        let e = t.expand_uri(&v).unwrap_err();
+        assert!(e.to_string().starts_with("template \"http://{+host}/{+path}\" with values {host=\"[::1\", path=\"a b,c\"} expanded to "));
+    }
+}
@@ -0,0 +1,210 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::Add;
+use std::vec::Vec;
+
+use super::types::{UriTemplate, UriTemplateComponent, UriTemplateOperator};
+
+
+#[derive(Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub enum UriTemplateJoinError {
+    /// The component would start a second fragment, as a `{#...}`
+    /// expression or a literal `#`.
+    DuplicateFragment(String),
+}
+
+impl fmt::Display for UriTemplateJoinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UriTemplateJoinError::DuplicateFragment(ref component) => write!(f, "{:?} starts a second fragment", component),
+        }
+    }
+}
+
+impl Error for UriTemplateJoinError {}
+
+
+// Tracks whether the components seen so far have started the query, with a
+// literal `?` or a `{?...}` expression, or the fragment of the URI.
+#[derive(Default)]
+struct JoinState {
+    query: bool,
+    literal_query: bool,
+    fragment: bool,
+}
+
+impl JoinState {
+    fn visit(&mut self, component: &UriTemplateComponent) -> Result<(), UriTemplateJoinError> {
+        match *component {
+            UriTemplateComponent::Literal(ref value) => {
+                let before_fragment = match value.find('#') {
+                    Some(position) => {
+                        if self.fragment {
+                            return Err(UriTemplateJoinError::DuplicateFragment(component.to_template_string()));
+                        }
+                        &value[..position]
+                    },
+                    None => &value[..],
+                };
+                if !self.fragment && before_fragment.contains('?') {
+                    self.query = true;
+                    self.literal_query = true;
+                }
+                if before_fragment.len() != value.len() {
+                    self.fragment = true;
+                }
+            },
+            UriTemplateComponent::Variable(Some(UriTemplateOperator::Fragment), _) => {
+                if self.fragment {
+                    return Err(UriTemplateJoinError::DuplicateFragment(component.to_template_string()));
+                }
+                self.fragment = true;
+            },
+            UriTemplateComponent::Variable(Some(UriTemplateOperator::QueryParameter), _) if !self.fragment => {
+                self.query = true;
+            },
+            UriTemplateComponent::Variable(_, _) => {},
+        }
+        Ok(())
+    }
+}
+
+impl UriTemplate {
+    /// Appends `other` to the template.
+    ///
+    /// A literal at the end of the template and one at the start of `other`
+    /// are combined. A `{?...}` expression that would start a second query
+    /// is combined with a query expression that ends the template, so that
+    /// `/search{?q}` joined with `{?page}` gives `/search{?q,page}`, or
+    /// rewritten to `{&...}` after a literal `?`, so that `/search?lang=en`
+    /// joined with `{?page}` gives `/search?lang=en{&page}`. Otherwise it is
+    /// left as it is, as a `{&...}` could start the query when the earlier
+    /// variables are undefined. Literal text is never rewritten.
+    ///
+    /// Fails if `other` starts a fragment when the template already has one.
+    pub fn join(&self, other: &UriTemplate) -> Result<UriTemplate, UriTemplateJoinError> {
+        let mut state = JoinState::default();
+        for component in self.components().iter() {
+            // A template that already has two fragments is left as it is.
+            let _ = state.visit(component);
+        }
+        let mut components: Vec<UriTemplateComponent> = self.components().to_vec();
+        for component in other.components().iter() {
+            let component = match *component {
+                UriTemplateComponent::Variable(Some(UriTemplateOperator::QueryParameter), ref variables) if state.query && !state.fragment => {
+                    match components.last_mut() {
+                        Some(&mut UriTemplateComponent::Variable(Some(UriTemplateOperator::QueryParameter), ref mut existing)) |
+                        Some(&mut UriTemplateComponent::Variable(Some(UriTemplateOperator::QueryContinuation), ref mut existing)) => {
+                            existing.extend(variables.iter().cloned());
+                            continue;
+                        },
+                        _ if state.literal_query => UriTemplateComponent::Variable(Some(UriTemplateOperator::QueryContinuation), variables.clone()),
+                        _ => component.clone(),
+                    }
+                },
+                ref component => component.clone(),
+            };
+            state.visit(&component)?;
+            match (components.last_mut(), component) {
+                (Some(UriTemplateComponent::Literal(previous)), UriTemplateComponent::Literal(value)) => previous.push_str(&value),
+                (_, component) => components.push(component),
+            }
+        }
+        Ok(UriTemplate::from_components(components))
+    }
+
+    /// Joins each template in turn with `join`, stopping at the first that
+    /// fails. The templates joined before it are kept.
+    pub fn try_extend<I: IntoIterator<Item=UriTemplate>>(&mut self, iter: I) -> Result<(), UriTemplateJoinError> {
+        for other in iter {
+            *self = self.join(&other)?;
+        }
+        Ok(())
+    }
+}
+
+/// Joins the templates with `UriTemplate::join`.
+impl<'a> Add<&'a UriTemplate> for UriTemplate {
+    type Output = Result<UriTemplate, UriTemplateJoinError>;
+
+    fn add(self, other: &'a UriTemplate) -> Result<UriTemplate, UriTemplateJoinError> {
+        self.join(other)
+    }
+}
+
+/// Joins the templates with `UriTemplate::join`.
+impl Add<UriTemplate> for UriTemplate {
+    type Output = Result<UriTemplate, UriTemplateJoinError>;
+
+    fn add(self, other: UriTemplate) -> Result<UriTemplate, UriTemplateJoinError> {
+        self.join(&other)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::{UriTemplate, UriTemplateValues};
+    use super::UriTemplateJoinError;
+
+    fn join(a: &str, b: &str) -> Result<String, UriTemplateJoinError> {
+        UriTemplate::parse(a).unwrap().join(&UriTemplate::parse(b).unwrap()).map(|t| t.into_template_string())
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(join("http://example.com/api", "/users/{id}"), Ok("http://example.com/api/users/{id}".to_string()));
+        assert_eq!(join("{base}/", "users"), Ok("{base}/users".to_string()));
+        assert_eq!(join("", "{x}"), Ok("{x}".to_string()));
+
+        let t = UriTemplate::parse("/api/").unwrap().join(&UriTemplate::parse("users").unwrap()).unwrap();
+        assert_eq!(t.components().len(), 1);
+    }
+
+    #[test]
+    fn test_join_query() {
+        assert_eq!(join("/search{?q}", "{?page}"), Ok("/search{?q,page}".to_string()));
+        assert_eq!(join("/search{?q}{&lang}", "{?page}"), Ok("/search{?q}{&lang,page}".to_string()));
+        assert_eq!(join("/search{?q}/x", "{?page}"), Ok("/search{?q}/x{?page}".to_string()));
+        assert_eq!(join("/search?lang=en", "{?q}{?page}"), Ok("/search?lang=en{&q,page}".to_string()));
+        assert_eq!(join("/search?lang=en", "/x{?q}"), Ok("/search?lang=en/x{&q}".to_string()));
+        assert_eq!(join("/search", "{?q}{?page}"), Ok("/search{?q,page}".to_string()));
+        assert_eq!(join("/search{#section}", "{?q}"), Ok("/search{#section}{?q}".to_string()));
+
+        let t = UriTemplate::parse("/search{?q}").unwrap().join(&UriTemplate::parse("{?page}").unwrap()).unwrap();
+        let mut v = UriTemplateValues::new();
+        v.set("page", "2");
+        assert_eq!(t.to_string_with_values(&v), "/search?page=2");
+        v.set("q", "cat");
+        assert_eq!(t.to_string_with_values(&v), "/search?q=cat&page=2");
+    }
+
+    #[test]
+    fn test_add() {
+        let t = (UriTemplate::parse("/search").unwrap() + UriTemplate::parse("{?q}").unwrap()).unwrap();
+        assert_eq!((t + &UriTemplate::parse("{?page}").unwrap()).map(|t| t.into_template_string()), Ok("/search{?q,page}".to_string()));
+        let joined = UriTemplate::parse("{#a}").unwrap() + UriTemplate::parse("{#b}").unwrap();
+        assert_eq!(joined, Err(UriTemplateJoinError::DuplicateFragment("{#b}".to_string())));
+    }
+
+    #[test]
+    fn test_try_extend() {
+        let mut t = UriTemplate::parse("/api").unwrap();
+        let others = vec!(UriTemplate::parse("/users").unwrap(), UriTemplate::parse("/{id}{?fields}").unwrap(), UriTemplate::parse("{?page}").unwrap());
+        assert_eq!(t.try_extend(others), Ok(()));
+        assert_eq!(t.to_template_string(), "/api/users/{id}{?fields,page}");
+
+        let mut t = UriTemplate::parse("/a").unwrap();
+        let others = vec!(UriTemplate::parse("{#x}").unwrap(), UriTemplate::parse("{#y}").unwrap(), UriTemplate::parse("/b").unwrap());
+        assert_eq!(t.try_extend(others), Err(UriTemplateJoinError::DuplicateFragment("{#y}".to_string())));
+        assert_eq!(t.to_template_string(), "/a{#x}");
+    }
+
+    #[test]
+    fn test_join_fragment() {
+        assert_eq!(join("/a{#x}", "/b"), Ok("/a{#x}/b".to_string()));
+        assert_eq!(join("/a{#x}", "{#y}"), Err(UriTemplateJoinError::DuplicateFragment("{#y}".to_string())));
+        assert_eq!(join("/a#top", "{#y}"), Err(UriTemplateJoinError::DuplicateFragment("{#y}".to_string())));
+        assert_eq!(join("/a{#x}", "#y"), Err(UriTemplateJoinError::DuplicateFragment("#y".to_string())));
+    }
+}
@@ -1,6 +1,46 @@
+#[cfg(feature = "http")]
+extern crate http;
+#[cfg(feature = "url")]
+extern crate url;
+#[cfg(feature = "idna")]
+extern crate idna;
+
 pub use types::{UriTemplate, UriTemplateOperator};
-pub use types::{UriTemplateValues, UriTemplateValue};
+pub use types::{UriTemplateComponent, UriTemplateVariable, UriTemplateExpansion};
+pub use types::{UriTemplateValues, UriTemplateValue, UriTemplateVars, ToUriTemplateValue};
 pub use building::{UriTemplateBuilder, UriTemplateComponentBuilder, UriTemplateModifier};
+pub use constraints::UriTemplateConstraint;
+pub use decoding::{FromUriTemplateValue, FromUriTemplateValues, UriTemplateMatchError};
+pub use parsing::{UriTemplateParseError, UriTemplateParseOptions};
+pub use expanding::{UriTemplateExpansionOptions, UriTemplateExpansionError, UriTemplateDotSegments};
+pub use expanding::{UriTemplateUntrustedPolicy, UriTemplateSpan};
+pub use escaping::{EscapeSet, iri_to_uri};
+pub use openapi::{OpenApiParameter, OpenApiParameterLocation, OpenApiParameterStyle, OpenApiConversionError};
+pub use routes::RoutePatternError;
+pub use regex_export::UriTemplateRegexOptions;
+pub use normalizing::UriTemplateNormalizeOptions;
+pub use joining::UriTemplateJoinError;
+pub use rewriting::UriTemplateRewriteError;
+#[cfg(feature = "http")]
+pub use http_uri::UriTemplateUriError;
+#[cfg(feature = "url")]
+pub use url_url::UriTemplateUrlError;
 
 mod types;
 mod building;
+mod constraints;
+mod parsing;
+mod expanding;
+mod escaping;
+mod normalizing;
+mod openapi;
+mod routes;
+mod regex_export;
+mod matching;
+mod decoding;
+mod joining;
+mod rewriting;
+#[cfg(feature = "http")]
+mod http_uri;
+#[cfg(feature = "url")]
+mod url_url;
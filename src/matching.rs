@@ -0,0 +1,395 @@
+use std::collections::{BTreeMap, HashSet};
+use std::vec::Vec;
+
+use super::regex_export::{self, ExpressionRules};
+use super::types::{UriTemplate, UriTemplateComponent, UriTemplateValue, UriTemplateValues, UriTemplateVariable, UriTemplateVars};
+
+
+const RESERVED: &str = ":/?#[]@!$&'()*+,;=";
+
+fn is_unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'-' || b == b'.' || b == b'_' || b == b'~'
+}
+
+fn is_pct_encoded(input: &[u8], position: usize) -> bool {
+    input.len() >= position + 3 && input[position] == b'%' && input[position + 1].is_ascii_hexdigit() && input[position + 2].is_ascii_hexdigit()
+}
+
+// The characters a value may consist of besides pct-encoded triplets, as in
+// `to_regex_string`.
+fn is_value_char(b: u8, rules: &ExpressionRules, explode: bool) -> bool {
+    if rules.reserved {
+        is_unreserved(b) || RESERVED.as_bytes().contains(&b)
+    } else {
+        is_unreserved(b) || b == b',' || (explode && (b == b'=' || rules.separator.as_bytes().contains(&b)))
+    }
+}
+
+fn pct_decode(value: &str) -> Option<String> {
+    let input = value.as_bytes();
+    let mut bytes: Vec<u8> = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if is_pct_encoded(input, i) {
+            bytes.push(u8::from_str_radix(&value[i + 1..i + 3], 16).unwrap());
+            i += 3;
+        } else {
+            bytes.push(input[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+
+#[derive(Copy,Clone)]
+enum Item<'a> {
+    Literal(&'a str),
+    // A variable, the rules of its expression and whether it is the first
+    // variable of the expression.
+    Variable(&'a UriTemplateVariable, ExpressionRules, bool),
+}
+
+struct Capture<'a> {
+    name: &'a str,
+    value: UriTemplateValue,
+    prefixed: bool,
+}
+
+// Whether two values captured for the same variable can be its value. A
+// prefixed value only has to be a prefix of the other.
+fn agrees(a: &Capture, b: &Capture) -> bool {
+    match (&a.value, &b.value) {
+        (UriTemplateValue::String(x), UriTemplateValue::String(y)) => match (a.prefixed, b.prefixed) {
+            (false, false) => x == y,
+            (true, false) => y.starts_with(x.as_str()),
+            (false, true) => x.starts_with(y.as_str()),
+            (true, true) => x.starts_with(y.as_str()) || y.starts_with(x.as_str()),
+        },
+        (x, y) => x == y,
+    }
+}
+
+// The state that decides whether the items from an index on match the URI
+// from a position on: the index, the position, whether an earlier variable
+// of the same expression matched and the captures of the variables that
+// appear again from the index on.
+type State<'a> = (usize, usize, bool, Vec<(&'a str, UriTemplateValue, bool)>);
+
+struct Matcher<'a, F: 'a> {
+    uri: &'a str,
+    items: Vec<Item<'a>>,
+    custom: &'a F,
+    captures: Vec<Capture<'a>>,
+    // States known not to match, so that backtracking never tries one
+    // twice and takes polynomial rather than exponential time.
+    failed: HashSet<State<'a>>,
+}
+
+impl<'a, F: Fn(&str, &str) -> bool> Matcher<'a, F> {
+    // The positions at which a value starting at `start` may end, shortest
+    // first.
+    fn value_ends(&self, start: usize, rules: &ExpressionRules, explode: bool) -> Vec<usize> {
+        let input = self.uri.as_bytes();
+        let mut ends = vec!(start);
+        let mut i = start;
+        loop {
+            if is_pct_encoded(input, i) {
+                i += 3;
+            } else if i < input.len() && is_value_char(input[i], rules, explode) {
+                i += 1;
+            } else {
+                return ends;
+            }
+            ends.push(i);
+        }
+    }
+
+    fn decode(&self, v: &UriTemplateVariable, raw: &str, rules: &ExpressionRules) -> Option<UriTemplateValue> {
+        let satisfies = |value: &str| v.constraint().map(|c| c.is_satisfied_by(value, self.custom)).unwrap_or(true);
+        if v.explode() {
+            let mut items: Vec<String> = vec!();
+            for item in raw.split(rules.separator) {
+                let item = match item.strip_prefix(v.name()) {
+                    Some("") if rules.named && !rules.ifemp => "",
+                    Some(rest) if rules.named && rest.starts_with('=') => &rest[1..],
+                    _ => item,
+                };
+                let item = pct_decode(item)?;
+                if !satisfies(&item) {
+                    return None;
+                }
+                items.push(item);
+            }
+            return Some(UriTemplateValue::List(items));
+        }
+        // Outside of reserved expansion a comma in a value is pct-encoded,
+        // so a raw one separates the items of an unexploded list.
+        if !rules.reserved && v.prefix().is_none() && raw.contains(',') {
+            let mut items: Vec<String> = vec!();
+            for item in raw.split(',') {
+                let item = pct_decode(item)?;
+                if !satisfies(&item) {
+                    return None;
+                }
+                items.push(item);
+            }
+            return Some(UriTemplateValue::List(items));
+        }
+        let value = pct_decode(raw)?;
+        if !satisfies(&value) || v.prefix().map(|prefix| value.chars().count() > prefix as usize).unwrap_or(false) {
+            return None;
+        }
+        Some(UriTemplateValue::String(value))
+    }
+
+    fn capture(&mut self, v: &'a UriTemplateVariable, raw: &str, rules: &ExpressionRules) -> bool {
+        let capture = match self.decode(v, raw, rules) {
+            Some(value) => Capture { name: v.name(), value, prefixed: v.prefix().is_some() },
+            None => return false,
+        };
+        if self.captures.iter().any(|c| c.name == capture.name && !agrees(c, &capture)) {
+            return false;
+        }
+        self.captures.push(capture);
+        true
+    }
+
+    fn state(&self, index: usize, position: usize, started: bool) -> State<'a> {
+        let items = &self.items[index..];
+        let started = started && match items.first() {
+            Some(&Item::Variable(_, _, first)) => !first,
+            _ => false,
+        };
+        let captures = self.captures.iter().filter(|c| items.iter().any(|item| match *item {
+            Item::Variable(v, _, _) => v.name() == c.name,
+            Item::Literal(_) => false,
+        })).map(|c| (c.name, c.value.clone(), c.prefixed)).collect();
+        (index, position, started, captures)
+    }
+
+    // Matches the items from `index` on against the URI from `position` on,
+    // trying shorter values first and undefined variables last. `started`
+    // tells whether an earlier variable of the expression matched, so that
+    // the next one is introduced by the separator rather than the prefix. A
+    // failure leaves the captures as they were.
+    fn match_from(&mut self, index: usize, position: usize, started: bool) -> bool {
+        let state = self.state(index, position, started);
+        if self.failed.contains(&state) {
+            return false;
+        }
+        let matched = self.try_match_from(index, position, state.2);
+        if !matched {
+            self.failed.insert(state);
+        }
+        matched
+    }
+
+    fn try_match_from(&mut self, index: usize, position: usize, started: bool) -> bool {
+        let item = match self.items.get(index) {
+            Some(item) => *item,
+            None => return position == self.uri.len(),
+        };
+        let (v, rules) = match item {
+            Item::Literal(literal) => {
+                return self.uri[position..].starts_with(literal) && self.match_from(index + 1, position + literal.len(), false);
+            },
+            Item::Variable(v, rules, _) => (v, rules),
+        };
+        let lead = if started { rules.separator } else { rules.prefix };
+        if self.uri[position..].starts_with(lead) && self.match_value(index, v, &rules, position + lead.len()) {
+            return true;
+        }
+        self.match_from(index + 1, position, started)
+    }
+
+    // Matches the variable at `index` as defined, with its value (and name,
+    // for named expressions) from `start` on, and the items after it.
+    fn match_value(&mut self, index: usize, v: &'a UriTemplateVariable, rules: &ExpressionRules, start: usize) -> bool {
+        let captures = self.captures.len();
+        let mut start = start;
+        if rules.named && !v.explode() {
+            if !self.uri[start..].starts_with(v.name()) {
+                return false;
+            }
+            start += v.name().len();
+            if !self.uri[start..].starts_with('=') {
+                if !rules.ifemp && self.capture(v, "", rules) && self.match_from(index + 1, start, true) {
+                    return true;
+                }
+                self.captures.truncate(captures);
+                return false;
+            }
+            start += 1;
+        }
+        for end in self.value_ends(start, rules, v.explode()) {
+            if v.explode() && end == start {
+                continue;
+            }
+            let uri = self.uri;
+            if self.capture(v, &uri[start..end], rules) && self.match_from(index + 1, end, true) {
+                return true;
+            }
+            self.captures.truncate(captures);
+        }
+        false
+    }
+}
+
+
+impl UriTemplate {
+    /// Matches `uri` against the template, returning the pct-decoded values
+    /// of the variables it defines, or `None` if it does not match.
+    ///
+    /// Matching follows `to_regex_string`: each variable is optional, and
+    /// where the split is ambiguous earlier variables take the shortest
+    /// value that lets the whole URI match. Values of constrained variables
+    /// must satisfy their constraints (see `UriTemplateConstraint`), so
+    /// `/users/{id:int}` does not match `/users/me`. Exploded variables are
+    /// returned as lists of their items, as are values with commas outside
+    /// of `+` and `#` expressions, split before pct-decoding so that `a%2Cb`
+    /// stays a single value. Undefined variables with a default
+    /// value (`{?format=json}`) take that value.
+    ///
+    /// `Custom` constraints are never satisfied, see `match_uri_with`.
+    pub fn match_uri(&self, uri: &str) -> Option<UriTemplateValues> {
+        self.match_uri_with(uri, |_, _| false)
+    }
+
+    /// Matches `uri` against the template as `match_uri` does, checking
+    /// `Custom` constraints by calling `custom` with their name and the
+    /// pct-decoded value.
+    pub fn match_uri_with<F: Fn(&str, &str) -> bool>(&self, uri: &str, custom: F) -> Option<UriTemplateValues> {
+        let mut items: Vec<Item> = vec!();
+        for component in self.components().iter() {
+            match *component {
+                UriTemplateComponent::Literal(ref value) => items.push(Item::Literal(value)),
+                UriTemplateComponent::Variable(operator, ref variables) => {
+                    let rules = regex_export::rules(operator);
+                    for (i, v) in variables.iter().enumerate() {
+                        items.push(Item::Variable(v, rules, i == 0));
+                    }
+                },
+            }
+        }
+        let mut matcher = Matcher { uri, items, custom: &custom, captures: vec!(), failed: HashSet::new() };
+        if !matcher.match_from(0, 0, false) {
+            return None;
+        }
+        // A variable that also appears with a prefix takes its full value.
+        let mut captures: BTreeMap<&str, Capture> = BTreeMap::new();
+        for capture in matcher.captures.into_iter() {
+            let replace = match (captures.get(capture.name), &capture.value) {
+                (Some(existing), UriTemplateValue::String(value)) => existing.prefixed && (!capture.prefixed || value.len() > existing.value.to_string().len()),
+                (Some(_), _) => false,
+                (None, _) => true,
+            };
+            if replace {
+                captures.insert(capture.name, capture);
+            }
+        }
+        let mut values = UriTemplateValues::new();
+        for (name, capture) in captures.into_iter() {
+            values.set(name, capture.value);
+        }
+        // Undefined variables take their default values, as in expansion.
+        for component in self.components().iter() {
+            if let UriTemplateComponent::Variable(_, ref variables) = *component {
+                for v in variables.iter() {
+                    if let Some(default) = v.default_value() {
+                        if values.uri_template_value(v.name()).is_none() {
+                            values.set(v.name(), default);
+                        }
+                    }
+                }
+            }
+        }
+        Some(values)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::{UriTemplate, UriTemplateComponent, UriTemplateParseOptions, UriTemplateValue, UriTemplateVars};
+
+    fn parse(template: &str) -> UriTemplate {
+        UriTemplate::parse_with_options(template, &UriTemplateParseOptions::new().strict(false)).unwrap()
+    }
+
+    fn matched(template: &str, uri: &str) -> Option<Vec<(String, String)>> {
+        let t = parse(template);
+        let values = t.match_uri(uri)?;
+        let mut names: Vec<&str> = vec!();
+        for component in t.components().iter() {
+            if let UriTemplateComponent::Variable(_, ref variables) = *component {
+                names.extend(variables.iter().map(|v| v.name()));
+            }
+        }
+        names.dedup();
+        Some(names.into_iter().filter_map(|name| {
+            values.uri_template_value(name).map(|value| (name.to_string(), value.to_string()))
+        }).collect())
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Option<Vec<(String, String)>> {
+        Some(pairs.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect())
+    }
+
+    #[test]
+    fn test_match_uri() {
+        assert_eq!(matched("/users/{id}", "/users/42"), pairs(&[("id", "42")]));
+        assert_eq!(matched("/users/{id}", "/posts/42"), None);
+        assert_eq!(matched("/search{?q,page}", "/search?q=a%20b&page=2"), pairs(&[("q", "a b"), ("page", "2")]));
+        assert_eq!(matched("/search{?q,page}", "/search?page=2"), pairs(&[("page", "2")]));
+        assert_eq!(matched("{+base}/api{/path*}", "http://example.com/api/a/b"), pairs(&[("base", "http://example.com"), ("path", "a,b")]));
+        assert_eq!(matched("{;x,y}", ";x=1;y"), pairs(&[("x", "1"), ("y", "")]));
+        assert_eq!(matched("/{var:3}/{var}", "/val/value"), pairs(&[("var", "value")]));
+        assert_eq!(matched("/{var}/{var}", "/a/b"), None);
+        assert_eq!(matched("/search{?q,page}", "/search?q=a?page=2"), None);
+        assert_eq!(matched("/search{?q,page}", "/search&page=2"), None);
+        assert_eq!(matched("{x,y}", ",b"), pairs(&[("x", ""), ("y", "b")]));
+        assert_eq!(matched("{x,y}", "b"), pairs(&[("x", "b")]));
+
+        let values = parse("{?list*}").match_uri("?list=a&list=b").unwrap();
+        assert_eq!(values.uri_template_value("list").unwrap().into_owned(), UriTemplateValue::List(vec!("a".to_string(), "b".to_string())));
+        let values = parse("/{list}/{string}{?x}").match_uri("/a,b%2Cc/d%2Ce?x=,").unwrap();
+        assert_eq!(values.uri_template_value("list").unwrap().into_owned(), UriTemplateValue::List(vec!("a".to_string(), "b,c".to_string())));
+        assert_eq!(values.uri_template_value("string").unwrap().into_owned(), UriTemplateValue::String("d,e".to_string()));
+        assert_eq!(values.uri_template_value("x").unwrap().into_owned(), UriTemplateValue::List(vec!("".to_string(), "".to_string())));
+        let values = parse("{+base}").match_uri("/a,b").unwrap();
+        assert_eq!(values.uri_template_value("base").unwrap().into_owned(), UriTemplateValue::String("/a,b".to_string()));
+    }
+
+    #[test]
+    fn test_match_uri_defaults() {
+        assert_eq!(matched("/report{?format=json}", "/report"), pairs(&[("format", "json")]));
+        assert_eq!(matched("/report{?format=json}", "/report?format=csv"), pairs(&[("format", "csv")]));
+        assert_eq!(matched("/report{?format=json}", "/report?format="), pairs(&[("format", "")]));
+    }
+
+    #[test]
+    fn test_match_uri_backtracking() {
+        let template: String = (0..12).map(|i| format!("{{v{}}}", i)).collect::<String>() + "/";
+        let uri = "a".repeat(60) + "!";
+        assert_eq!(parse(&template).match_uri(&uri), None);
+        assert!(parse("{a}{b}{a}").match_uri("xyx").is_some());
+        assert_eq!(parse("{a}{b}{a}/").match_uri(&(uri + "/")), None);
+    }
+
+    #[test]
+    fn test_match_uri_constraints() {
+        assert_eq!(matched("/users/{id:int}", "/users/42"), pairs(&[("id", "42")]));
+        assert_eq!(matched("/users/{id:int}", "/users/me"), None);
+        assert_eq!(matched("/files/{id:uuid}", "/files/123e4567-e89b-12d3-a456-426614174000"), pairs(&[("id", "123e4567-e89b-12d3-a456-426614174000")]));
+        assert_eq!(matched("/report{.format:(json|csv)}", "/report.csv"), pairs(&[("format", "csv")]));
+        assert_eq!(matched("/report{.format:(json|csv)}", "/report.xml"), None);
+        assert_eq!(matched("/{ids*:int}", "/1,2,x"), None);
+
+        let t = parse("/posts/{slug:slug}");
+        assert_eq!(t.match_uri("/posts/hello-world"), None);
+        let slug = |name: &str, value: &str| name == "slug" && value.bytes().all(|b| b.is_ascii_lowercase() || b == b'-');
+        assert!(t.match_uri_with("/posts/hello-world", slug).is_some());
+        assert!(t.match_uri_with("/posts/Hello", slug).is_none());
+    }
+}
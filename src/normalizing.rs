@@ -0,0 +1,211 @@
+use std::vec::Vec;
+
+use super::types::{UriTemplate, UriTemplateComponent, UriTemplateOperator};
+
+
+fn uppercase_pct_encoding(literal: &str) -> String {
+    let input = literal.as_bytes();
+    let mut s = String::with_capacity(literal.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] == b'%' && i + 2 < input.len() && input[i + 1].is_ascii_hexdigit() && input[i + 2].is_ascii_hexdigit() {
+            s.push('%');
+            s.push(input[i + 1].to_ascii_uppercase() as char);
+            s.push(input[i + 2].to_ascii_uppercase() as char);
+            i += 3;
+        } else {
+            let c = literal[i..].chars().next().unwrap();
+            s.push(c);
+            i += c.len_utf8();
+        }
+    }
+    s
+}
+
+/// Options for `UriTemplate::normalize_with_options`.
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub struct UriTemplateNormalizeOptions {
+    merge_query: bool,
+}
+
+impl Default for UriTemplateNormalizeOptions {
+    fn default() -> UriTemplateNormalizeOptions {
+        UriTemplateNormalizeOptions::new()
+    }
+}
+
+impl UriTemplateNormalizeOptions {
+    pub fn new() -> UriTemplateNormalizeOptions {
+        UriTemplateNormalizeOptions {
+            merge_query: false,
+        }
+    }
+
+    /// When set, `{?a}{&b}` is merged into `{?a,b}`. The two agree whenever
+    /// any variable of the first expression is defined; when none is, the
+    /// original produces `&b=...` without a preceding `?`, while the merged
+    /// form produces the arguably intended `?b=...`. So this rewrite changes
+    /// the expansion, and is not done by default.
+    pub fn merge_query(mut self, merge_query: bool) -> UriTemplateNormalizeOptions {
+        self.merge_query = merge_query;
+        self
+    }
+}
+
+
+// Whether `{<first>a}{<second>b}` may be rewritten as `{<first>a,b}`.
+fn mergeable(first: Option<UriTemplateOperator>, second: Option<UriTemplateOperator>, options: &UriTemplateNormalizeOptions) -> bool {
+    match (first, second) {
+        (Some(UriTemplateOperator::QueryParameter), Some(UriTemplateOperator::QueryContinuation)) => options.merge_query,
+        (Some(first), Some(second)) => first == second && matches!(first,
+            UriTemplateOperator::PathExtension | UriTemplateOperator::PathComponent |
+            UriTemplateOperator::PathParameter | UriTemplateOperator::QueryContinuation),
+        _ => false,
+    }
+}
+
+impl UriTemplate {
+    /// Returns the canonical form of the template, as
+    /// `normalize_with_options` with the default options does.
+    pub fn normalize(&self) -> UriTemplate {
+        self.normalize_with_options(&UriTemplateNormalizeOptions::new())
+    }
+
+    /// Returns the canonical form of the template.
+    ///
+    /// These rewrites never change the expansion for any values:
+    ///
+    /// - empty literals are removed and adjacent literals are combined;
+    /// - adjacent `{.a}{.b}`, `{/a}{/b}`, `{;a}{;b}` and `{&a}{&b}`
+    ///   expressions are merged into `{.a,b}` and so on, since for these
+    ///   operators the prefix is the same as the separator.
+    ///
+    /// Hex digits of pct-encoded triplets in the combined literals are also
+    /// uppercased. Literals are expanded as they are, so this changes the
+    /// bytes of the expansion, though RFC 3986 section 6.2.2.1 treats the
+    /// two forms as equivalent URIs.
+    ///
+    /// With `UriTemplateNormalizeOptions::merge_query`, `{?a}{&b}` is merged
+    /// into `{?a,b}` too, which changes the expansion when `a` is undefined.
+    ///
+    /// Expressions are otherwise left alone; in particular `{a}{b}` is not
+    /// the same as `{a,b}`, nor `{?a}{?b}` the same as `{?a,b}`.
+    pub fn normalize_with_options(&self, options: &UriTemplateNormalizeOptions) -> UriTemplate {
+        let mut components: Vec<UriTemplateComponent> = vec!();
+        for component in self.components().iter() {
+            let merged = match (components.last_mut(), component) {
+                (Some(UriTemplateComponent::Literal(previous)), UriTemplateComponent::Literal(value)) => {
+                    previous.push_str(value);
+                    true
+                },
+                (Some(UriTemplateComponent::Variable(previous_operator, previous)), UriTemplateComponent::Variable(operator, variables)) if mergeable(*previous_operator, *operator, options) => {
+                    previous.extend(variables.iter().cloned());
+                    true
+                },
+                (_, UriTemplateComponent::Literal(value)) if value.is_empty() => true,
+                _ => false,
+            };
+            if !merged {
+                components.push(component.clone());
+            }
+        }
+        // Triplets may only be complete once literals are combined.
+        for component in components.iter_mut() {
+            if let UriTemplateComponent::Literal(ref mut value) = *component {
+                *value = uppercase_pct_encoding(value);
+            }
+        }
+        UriTemplate::from_components(components)
+    }
+
+    /// Compares the normalized forms of two templates; see
+    /// `normalize_with_options` for exactly which differences are ignored.
+    /// Equivalent templates expand to equivalent URIs for any values.
+    pub fn is_equivalent(&self, other: &UriTemplate) -> bool {
+        self.normalize() == other.normalize()
+    }
+
+    /// Compares the forms of two templates normalized with `options`, so
+    /// that with `merge_query` `{?a}{&b}` is equivalent to `{?a,b}`.
+    pub fn is_equivalent_with_options(&self, other: &UriTemplate, options: &UriTemplateNormalizeOptions) -> bool {
+        self.normalize_with_options(options) == other.normalize_with_options(options)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::{UriTemplate, UriTemplateBuilder, UriTemplateNormalizeOptions, UriTemplateValues};
+
+    fn normalize(template: &str) -> String {
+        UriTemplate::parse(template).unwrap().normalize().into_template_string()
+    }
+
+    #[test]
+    fn test_literals() {
+        let t = UriTemplateBuilder::new()
+            .literal("http://example.com/")
+            .literal("")
+            .literal("%7efoo%2F")
+            .into_uri_template();
+        assert_eq!(t.normalize().to_template_string(), "http://example.com/%7Efoo%2F");
+        assert_eq!(t.normalize().components().len(), 1);
+
+        let t = UriTemplateBuilder::new()
+            .literal("/%7")
+            .literal("e")
+            .into_uri_template();
+        assert_eq!(t.normalize().to_template_string(), "/%7E");
+    }
+
+    #[test]
+    fn test_merge_expressions() {
+        assert_eq!(normalize("{/a}{/b,c*}"), "{/a,b,c*}");
+        assert_eq!(normalize("X{.a}{.b}"), "X{.a,b}");
+        assert_eq!(normalize("{;a}{;b:3}"), "{;a,b:3}");
+        assert_eq!(normalize("{?a}{&b}{&c}"), "{?a}{&b,c}");
+        let merge_query = UriTemplateNormalizeOptions::new().merge_query(true);
+        assert_eq!(UriTemplate::parse("{?a}{&b}{&c}").unwrap().normalize_with_options(&merge_query).to_template_string(), "{?a,b,c}");
+        assert_eq!(normalize("{&a}{&b}"), "{&a,b}");
+    }
+
+    #[test]
+    fn test_unmergeable() {
+        assert_eq!(normalize("{a}{b}"), "{a}{b}");
+        assert_eq!(normalize("{+a}{+b}"), "{+a}{+b}");
+        assert_eq!(normalize("{#a}{#b}"), "{#a}{#b}");
+        assert_eq!(normalize("{?a}{?b}"), "{?a}{?b}");
+        assert_eq!(normalize("{/a}/{/b}"), "{/a}/{/b}");
+        assert_eq!(normalize("{/a}{.b}"), "{/a}{.b}");
+    }
+
+    #[test]
+    fn test_exact_rewrites_preserve_expansion() {
+        let mut v = UriTemplateValues::new();
+        v.set("a", "1");
+        v.set("c", ["x", "y"].as_ref());
+        v.set("e", "");
+        for template in ["{/a}{/b}{/c*}", "{/b}{/c}", "{.b}{.a}", "{;e}{;b}{;a}", "{&b}{&a}", "{?b}{&a}", "{?a}{&b}{&c}"].iter() {
+            let t = UriTemplate::parse(template).unwrap();
+            assert_eq!(t.to_string_with_values(&v), t.normalize().to_string_with_values(&v), "{}", template);
+        }
+
+        let t = UriTemplate::parse("{?b}{&a}").unwrap();
+        let merged = t.normalize_with_options(&UriTemplateNormalizeOptions::new().merge_query(true));
+        assert_eq!(t.to_string_with_values(&v), "&a=1");
+        assert_eq!(merged.to_string_with_values(&v), "?a=1");
+    }
+
+    #[test]
+    fn test_is_equivalent() {
+        let a = UriTemplate::parse("/orders{?a}{&b}").unwrap();
+        let b = UriTemplate::parse("/orders{?a,b}").unwrap();
+        let c = UriTemplate::parse("/orders{?b,a}").unwrap();
+        assert!(!a.is_equivalent(&b));
+        assert!(!a.is_equivalent(&c));
+        let merge_query = UriTemplateNormalizeOptions::new().merge_query(true);
+        assert!(a.is_equivalent_with_options(&b, &merge_query));
+        assert!(!a.is_equivalent_with_options(&c, &merge_query));
+        assert!(UriTemplate::parse("/%7e{x}").unwrap().is_equivalent(&UriTemplate::parse("/%7E{x}").unwrap()));
+    }
+}
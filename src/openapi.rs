@@ -0,0 +1,335 @@
+use std::error::Error;
+use std::fmt;
+use std::vec::Vec;
+
+use super::parsing::{self, UriTemplateParseError};
+use super::types::{UriTemplate, UriTemplateComponent, UriTemplateOperator};
+
+
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub enum OpenApiParameterLocation {
+    Path,
+    Query,
+    Header,
+    Cookie,
+}
+
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub enum OpenApiParameterStyle {
+    Matrix,
+    Label,
+    Form,
+    Simple,
+    SpaceDelimited,
+    PipeDelimited,
+    DeepObject,
+}
+
+impl OpenApiParameterStyle {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            OpenApiParameterStyle::Matrix => "matrix",
+            OpenApiParameterStyle::Label => "label",
+            OpenApiParameterStyle::Form => "form",
+            OpenApiParameterStyle::Simple => "simple",
+            OpenApiParameterStyle::SpaceDelimited => "spaceDelimited",
+            OpenApiParameterStyle::PipeDelimited => "pipeDelimited",
+            OpenApiParameterStyle::DeepObject => "deepObject",
+        }
+    }
+}
+
+
+/// The parts of an OpenAPI 3 Parameter Object that affect the URI.
+#[derive(Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub struct OpenApiParameter {
+    name: String,
+    location: OpenApiParameterLocation,
+    style: Option<OpenApiParameterStyle>,
+    explode: Option<bool>,
+}
+
+impl OpenApiParameter {
+    pub fn new(name: &str, location: OpenApiParameterLocation) -> OpenApiParameter {
+        OpenApiParameter {
+            name: name.to_string(),
+            location,
+            style: None,
+            explode: None,
+        }
+    }
+
+    pub fn with_style(mut self, style: OpenApiParameterStyle) -> OpenApiParameter {
+        self.style = Some(style);
+        self
+    }
+
+    pub fn with_explode(mut self, explode: bool) -> OpenApiParameter {
+        self.explode = Some(explode);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn location(&self) -> OpenApiParameterLocation {
+        self.location
+    }
+
+    /// The declared style, or the default for the parameter's location.
+    pub fn style(&self) -> OpenApiParameterStyle {
+        self.style.unwrap_or(match self.location {
+            OpenApiParameterLocation::Path => OpenApiParameterStyle::Simple,
+            OpenApiParameterLocation::Query => OpenApiParameterStyle::Form,
+            OpenApiParameterLocation::Header => OpenApiParameterStyle::Simple,
+            OpenApiParameterLocation::Cookie => OpenApiParameterStyle::Form,
+        })
+    }
+
+    /// The declared explode flag, or the default for the parameter's style.
+    pub fn explode(&self) -> bool {
+        self.explode.unwrap_or(self.style() == OpenApiParameterStyle::Form)
+    }
+}
+
+
+#[derive(Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub enum OpenApiConversionError {
+    /// The path is not a valid template once parameters are substituted.
+    InvalidPath(UriTemplateParseError),
+    /// The parameter name cannot be used as an RFC 6570 varname.
+    InvalidVariableName(String),
+    /// A `{name}` in the path has no matching path parameter.
+    UndeclaredPathParameter(String),
+    /// A declared path parameter does not appear in the path.
+    UnusedPathParameter(String),
+    /// The parameter's style has no RFC 6570 equivalent in its location.
+    UnsupportedStyle(String, OpenApiParameterStyle),
+    /// A template component with no OpenAPI equivalent.
+    UnsupportedComponent(String),
+}
+
+impl fmt::Display for OpenApiConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OpenApiConversionError::InvalidPath(ref e) => write!(f, "invalid path: {}", e),
+            OpenApiConversionError::InvalidVariableName(ref name) => write!(f, "parameter name {:?} is not a valid template variable name", name),
+            OpenApiConversionError::UndeclaredPathParameter(ref name) => write!(f, "path parameter {:?} is not declared", name),
+            OpenApiConversionError::UnusedPathParameter(ref name) => write!(f, "path parameter {:?} does not appear in the path", name),
+            OpenApiConversionError::UnsupportedStyle(ref name, style) => write!(f, "parameter {:?} uses style {:?}, which URI templates cannot represent", name, style.name()),
+            OpenApiConversionError::UnsupportedComponent(ref component) => write!(f, "{:?} cannot be represented in an OpenAPI path", component),
+        }
+    }
+}
+
+impl Error for OpenApiConversionError {}
+
+
+fn expression(operator: &str, name: &str, explode: bool) -> String {
+    format!("{{{}{}{}}}", operator, name, if explode { "*" } else { "" })
+}
+
+impl UriTemplate {
+    /// Builds a template from an OpenAPI path and its parameters.
+    ///
+    /// Path parameters map `simple` to `{name}`, `label` to `{.name}` and
+    /// `matrix` to `{;name}`. `form` query parameters are collected into a
+    /// single `{?...}` expression in declaration order. Header and cookie
+    /// parameters are ignored.
+    pub fn from_openapi(path: &str, parameters: &[OpenApiParameter]) -> Result<UriTemplate, OpenApiConversionError> {
+        for p in parameters.iter().filter(|p| p.location == OpenApiParameterLocation::Path || p.location == OpenApiParameterLocation::Query) {
+            if !parsing::is_valid_varname(&p.name) {
+                return Err(OpenApiConversionError::InvalidVariableName(p.name.clone()));
+            }
+        }
+
+        let mut template = String::new();
+        let mut used: Vec<&str> = vec!();
+        let mut rest = path;
+        while let Some(start) = rest.find('{') {
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => break,
+            };
+            let name = &rest[start + 1..end];
+            let p = parameters.iter()
+                .find(|p| p.location == OpenApiParameterLocation::Path && p.name == name)
+                .ok_or_else(|| OpenApiConversionError::UndeclaredPathParameter(name.to_string()))?;
+            let operator = match p.style() {
+                OpenApiParameterStyle::Simple => "",
+                OpenApiParameterStyle::Label => ".",
+                OpenApiParameterStyle::Matrix => ";",
+                style => return Err(OpenApiConversionError::UnsupportedStyle(p.name.clone(), style)),
+            };
+            template.push_str(&rest[..start]);
+            template.push_str(&expression(operator, name, p.explode()));
+            used.push(name);
+            rest = &rest[end + 1..];
+        }
+        template.push_str(rest);
+
+        if let Some(p) = parameters.iter().find(|p| p.location == OpenApiParameterLocation::Path && !used.contains(&p.name.as_str())) {
+            return Err(OpenApiConversionError::UnusedPathParameter(p.name.clone()));
+        }
+
+        let mut query: Vec<String> = vec!();
+        for p in parameters.iter().filter(|p| p.location == OpenApiParameterLocation::Query) {
+            match p.style() {
+                OpenApiParameterStyle::Form => query.push(format!("{}{}", p.name, if p.explode() { "*" } else { "" })),
+                style => return Err(OpenApiConversionError::UnsupportedStyle(p.name.clone(), style)),
+            }
+        }
+        if !query.is_empty() {
+            template.push_str(&format!("{{?{}}}", query.join(",")));
+        }
+
+        UriTemplate::parse(&template).map_err(OpenApiConversionError::InvalidPath)
+    }
+
+    /// Converts the template to an OpenAPI path and parameter list, the
+    /// inverse of `from_openapi`.
+    ///
+    /// Only single-variable `{name}`, `{.name}` and `{;name}` expressions in
+    /// the path and trailing `{?...}`/`{&...}` expressions are representable.
+    /// Variables with a prefix, a constraint or a default value are not.
+    pub fn to_openapi(&self) -> Result<(String, Vec<OpenApiParameter>), OpenApiConversionError> {
+        let mut path = String::new();
+        let mut parameters: Vec<OpenApiParameter> = vec!();
+        let mut in_query = false;
+        for component in self.components().iter() {
+            let unsupported = || OpenApiConversionError::UnsupportedComponent(component.to_template_string());
+            match *component {
+                UriTemplateComponent::Literal(ref value) => {
+                    if in_query || value.contains('?') || value.contains('#') {
+                        return Err(unsupported());
+                    }
+                    path.push_str(value);
+                },
+                UriTemplateComponent::Variable(operator, ref variables) => {
+                    if variables.iter().any(|v| v.prefix().is_some() || v.constraint().is_some() || v.default_value().is_some()) {
+                        return Err(unsupported());
+                    }
+                    let style = match operator {
+                        None => OpenApiParameterStyle::Simple,
+                        Some(UriTemplateOperator::PathExtension) => OpenApiParameterStyle::Label,
+                        Some(UriTemplateOperator::PathParameter) => OpenApiParameterStyle::Matrix,
+                        Some(UriTemplateOperator::QueryParameter) | Some(UriTemplateOperator::QueryContinuation) => {
+                            in_query = true;
+                            for v in variables.iter() {
+                                parameters.push(OpenApiParameter::new(v.name(), OpenApiParameterLocation::Query)
+                                    .with_style(OpenApiParameterStyle::Form)
+                                    .with_explode(v.explode()));
+                            }
+                            continue;
+                        },
+                        _ => return Err(unsupported()),
+                    };
+                    if in_query || variables.len() != 1 {
+                        return Err(unsupported());
+                    }
+                    let v = &variables[0];
+                    path.push_str(&format!("{{{}}}", v.name()));
+                    parameters.push(OpenApiParameter::new(v.name(), OpenApiParameterLocation::Path)
+                        .with_style(style)
+                        .with_explode(v.explode()));
+                },
+            }
+        }
+        Ok((path, parameters))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::{UriTemplate, UriTemplateParseOptions, UriTemplateValues};
+    use super::{OpenApiConversionError, OpenApiParameter, OpenApiParameterLocation, OpenApiParameterStyle};
+
+    fn path(name: &str) -> OpenApiParameter {
+        OpenApiParameter::new(name, OpenApiParameterLocation::Path)
+    }
+
+    fn query(name: &str) -> OpenApiParameter {
+        OpenApiParameter::new(name, OpenApiParameterLocation::Query)
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(path("id").style(), OpenApiParameterStyle::Simple);
+        assert!(!path("id").explode());
+        assert_eq!(query("q").style(), OpenApiParameterStyle::Form);
+        assert!(query("q").explode());
+        assert!(!query("q").with_explode(false).explode());
+    }
+
+    #[test]
+    fn test_from_openapi() {
+        let t = UriTemplate::from_openapi("/users/{id}/files{format}/{coords}", &[
+            path("id"),
+            path("format").with_style(OpenApiParameterStyle::Label),
+            path("coords").with_style(OpenApiParameterStyle::Matrix).with_explode(true),
+            query("fields").with_explode(false),
+            query("tags"),
+            OpenApiParameter::new("X-Request-Id", OpenApiParameterLocation::Header),
+        ]).unwrap();
+        assert_eq!(t.to_template_string(), "/users/{id}/files{.format}/{;coords*}{?fields,tags*}");
+
+        let mut v = UriTemplateValues::new();
+        v.set("id", "7");
+        v.set("format", "json");
+        v.set("coords", ["1", "2"].as_ref());
+        v.set("fields", ["a", "b"].as_ref());
+        v.set("tags", ["x", "y"].as_ref());
+        assert_eq!(t.to_string_with_values(&v), "/users/7/files.json/;coords=1;coords=2?fields=a,b&tags=x&tags=y");
+    }
+
+    #[test]
+    fn test_from_openapi_errors() {
+        assert_eq!(UriTemplate::from_openapi("/items", &[query("filter").with_style(OpenApiParameterStyle::DeepObject)]),
+            Err(OpenApiConversionError::UnsupportedStyle("filter".to_string(), OpenApiParameterStyle::DeepObject)));
+        assert_eq!(UriTemplate::from_openapi("/items", &[query("ids").with_style(OpenApiParameterStyle::PipeDelimited)]),
+            Err(OpenApiConversionError::UnsupportedStyle("ids".to_string(), OpenApiParameterStyle::PipeDelimited)));
+        assert_eq!(UriTemplate::from_openapi("/items/{id}", &[path("id").with_style(OpenApiParameterStyle::Form)]),
+            Err(OpenApiConversionError::UnsupportedStyle("id".to_string(), OpenApiParameterStyle::Form)));
+        assert_eq!(UriTemplate::from_openapi("/items/{id}", &[]),
+            Err(OpenApiConversionError::UndeclaredPathParameter("id".to_string())));
+        assert_eq!(UriTemplate::from_openapi("/items", &[path("id")]),
+            Err(OpenApiConversionError::UnusedPathParameter("id".to_string())));
+        assert_eq!(UriTemplate::from_openapi("/items/{item-id}", &[path("item-id")]),
+            Err(OpenApiConversionError::InvalidVariableName("item-id".to_string())));
+        assert!(UriTemplate::from_openapi("/items/{id", &[]).is_err());
+    }
+
+    #[test]
+    fn test_to_openapi() {
+        let t = UriTemplate::parse("/users/{id}/files{.format}/{;coords*}{?fields,tags*}").unwrap();
+        let (p, parameters) = t.to_openapi().unwrap();
+        assert_eq!(p, "/users/{id}/files{format}/{coords}");
+        assert_eq!(parameters, vec!(
+            path("id").with_style(OpenApiParameterStyle::Simple).with_explode(false),
+            path("format").with_style(OpenApiParameterStyle::Label).with_explode(false),
+            path("coords").with_style(OpenApiParameterStyle::Matrix).with_explode(true),
+            query("fields").with_style(OpenApiParameterStyle::Form).with_explode(false),
+            query("tags").with_style(OpenApiParameterStyle::Form).with_explode(true),
+        ));
+        assert_eq!(UriTemplate::from_openapi(&p, &parameters).unwrap(), t);
+    }
+
+    #[test]
+    fn test_to_openapi_errors() {
+        let unsupported = |template: &str, component: &str| {
+            let options = UriTemplateParseOptions::new().strict(false);
+            assert_eq!(UriTemplate::parse_with_options(template, &options).unwrap().to_openapi(),
+                Err(OpenApiConversionError::UnsupportedComponent(component.to_string())));
+        };
+        unsupported("/files{/path*}", "{/path*}");
+        unsupported("/files/{+path}", "{+path}");
+        unsupported("/files/{x,y}", "{x,y}");
+        unsupported("/files/{hash:7}", "{hash:7}");
+        unsupported("/users/{id:int}", "{id:int}");
+        unsupported("/users{?page=1}", "{?page=1}");
+        unsupported("/items{?page}/more", "/more");
+        unsupported("/items?fixed=1{&page}", "/items?fixed=1");
+    }
+}
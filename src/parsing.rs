@@ -0,0 +1,627 @@
+use std::error::Error;
+use std::fmt;
+use std::vec::Vec;
+
+use super::constraints::UriTemplateConstraint;
+use super::types::{UriTemplate, UriTemplateComponent, UriTemplateOperator, UriTemplateVariable};
+
+
+#[derive(Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub enum UriTemplateParseError {
+    InvalidLiteralCharacter(usize, char),
+    InvalidPercentEncoding(usize),
+    UnterminatedExpression(usize),
+    UnexpectedCharacter(usize, char),
+    ReservedOperator(usize, char),
+    EmptyVariableName(usize),
+    InvalidPrefix(usize),
+    /// The template is longer than `UriTemplateParseOptions::max_length`;
+    /// the character starting here is the first past the limit.
+    TemplateTooLong(usize),
+    /// The expression starting here is one more than
+    /// `UriTemplateParseOptions::max_expressions` allows.
+    TooManyExpressions(usize),
+    /// The varspec starting here is one more than
+    /// `UriTemplateParseOptions::max_variables_per_expression` allows.
+    TooManyVariables(usize),
+    /// The template uses an extension to RFC 6570, which is only accepted
+    /// with `UriTemplateParseOptions::strict(false)`.
+    UnsupportedExtension(usize),
+    /// The constraint starting here is malformed.
+    InvalidConstraint(usize),
+    /// The default value starting here does not satisfy the variable's
+    /// constraint.
+    InvalidDefault(usize),
+}
+
+impl UriTemplateParseError {
+    /// Byte offset into the template at which the error was detected.
+    pub fn position(&self) -> usize {
+        match *self {
+            UriTemplateParseError::InvalidLiteralCharacter(position, _) => position,
+            UriTemplateParseError::InvalidPercentEncoding(position) => position,
+            UriTemplateParseError::UnterminatedExpression(position) => position,
+            UriTemplateParseError::UnexpectedCharacter(position, _) => position,
+            UriTemplateParseError::ReservedOperator(position, _) => position,
+            UriTemplateParseError::EmptyVariableName(position) => position,
+            UriTemplateParseError::InvalidPrefix(position) => position,
+            UriTemplateParseError::TemplateTooLong(position) => position,
+            UriTemplateParseError::TooManyExpressions(position) => position,
+            UriTemplateParseError::TooManyVariables(position) => position,
+            UriTemplateParseError::UnsupportedExtension(position) => position,
+            UriTemplateParseError::InvalidConstraint(position) => position,
+            UriTemplateParseError::InvalidDefault(position) => position,
+        }
+    }
+}
+
+impl fmt::Display for UriTemplateParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UriTemplateParseError::InvalidLiteralCharacter(position, c) => write!(f, "invalid character {:?} in literal at offset {}", c, position),
+            UriTemplateParseError::InvalidPercentEncoding(position) => write!(f, "invalid percent-encoding at offset {}", position),
+            UriTemplateParseError::UnterminatedExpression(position) => write!(f, "unterminated expression starting at offset {}", position),
+            UriTemplateParseError::UnexpectedCharacter(position, c) => write!(f, "unexpected character {:?} in expression at offset {}", c, position),
+            UriTemplateParseError::ReservedOperator(position, c) => write!(f, "reserved operator {:?} at offset {}", c, position),
+            UriTemplateParseError::EmptyVariableName(position) => write!(f, "missing variable name at offset {}", position),
+            UriTemplateParseError::InvalidPrefix(position) => write!(f, "invalid prefix length at offset {}", position),
+            UriTemplateParseError::TemplateTooLong(position) => write!(f, "template exceeds the maximum length at offset {}", position),
+            UriTemplateParseError::TooManyExpressions(position) => write!(f, "too many expressions at offset {}", position),
+            UriTemplateParseError::TooManyVariables(position) => write!(f, "too many variables in expression at offset {}", position),
+            UriTemplateParseError::UnsupportedExtension(position) => write!(f, "extension to RFC 6570 at offset {} in a strict template", position),
+            UriTemplateParseError::InvalidConstraint(position) => write!(f, "invalid constraint at offset {}", position),
+            UriTemplateParseError::InvalidDefault(position) => write!(f, "default value at offset {} does not satisfy the constraint", position),
+        }
+    }
+}
+
+impl Error for UriTemplateParseError {}
+
+
+/// Options for `UriTemplate::parse_with_options`: limits, for templates
+/// from untrusted sources, and whether to accept extensions to RFC 6570.
+/// By default there are no limits and only RFC 6570 templates are accepted.
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub struct UriTemplateParseOptions {
+    max_length: Option<usize>,
+    max_expressions: Option<usize>,
+    max_variables_per_expression: Option<usize>,
+    strict: bool,
+}
+
+impl Default for UriTemplateParseOptions {
+    fn default() -> UriTemplateParseOptions {
+        UriTemplateParseOptions::new()
+    }
+}
+
+impl UriTemplateParseOptions {
+    pub fn new() -> UriTemplateParseOptions {
+        UriTemplateParseOptions {
+            max_length: None,
+            max_expressions: None,
+            max_variables_per_expression: None,
+            strict: true,
+        }
+    }
+
+    /// The maximum length of the template in bytes.
+    pub fn max_length(mut self, max_length: usize) -> UriTemplateParseOptions {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    /// The maximum number of `{...}` expressions in the template.
+    pub fn max_expressions(mut self, max_expressions: usize) -> UriTemplateParseOptions {
+        self.max_expressions = Some(max_expressions);
+        self
+    }
+
+    /// The maximum number of variables in each expression.
+    pub fn max_variables_per_expression(mut self, max_variables_per_expression: usize) -> UriTemplateParseOptions {
+        self.max_variables_per_expression = Some(max_variables_per_expression);
+        self
+    }
+
+    /// Whether to reject extensions to RFC 6570 with `UnsupportedExtension`,
+    /// the default. When not strict, varspecs may have a default value,
+    /// written `{name=default}` or `{?format=json}`, that is expanded when
+    /// the variable is undefined. The default runs up to the next `,` or
+    /// `}` and may contain the characters allowed in literals. It is
+    /// expanded as a value would be, so pct-encoded triplets in it are only
+    /// left as they are by `{+...}` and `{#...}`.
+    ///
+    /// Varspecs may also have a constraint, written after any modifier as
+    /// `{id:int}`, `{id:uuid}`, `{format:(json|xml)}` or the name of a
+    /// custom constraint such as `{path*:slug}`, see
+    /// `UriTemplateConstraint`. A variable with a prefix modifier cannot
+    /// have a constraint.
+    pub fn strict(mut self, strict: bool) -> UriTemplateParseOptions {
+        self.strict = strict;
+        self
+    }
+}
+
+
+// RFC 6570 section 2.1, excluding the ASCII characters that are never
+// allowed in a literal and the pct-encoded triplets handled separately.
+fn is_literal_char(c: char) -> bool {
+    match c {
+        '\u{0}'..='\u{20}' | '\u{7f}' => false,
+        '"' | '%' | '\'' | '<' | '>' | '\\' | '^' | '`' | '{' | '|' | '}' => false,
+        c if c.is_ascii() => true,
+        c => is_ucschar(c) || is_iprivate(c),
+    }
+}
+
+// RFC 3987 section 2.2.
+pub(crate) fn is_ucschar(c: char) -> bool {
+    match c as u32 {
+        0xA0..=0xD7FF | 0xF900..=0xFDCF | 0xFDF0..=0xFFEF | 0xE1000..=0xEFFFD => true,
+        c if (0x10000..=0xDFFFD).contains(&c) => (c & 0xFFFF) < 0xFFFE,
+        _ => false,
+    }
+}
+
+// RFC 3987 section 2.2.
+pub(crate) fn is_iprivate(c: char) -> bool {
+    matches!(c as u32, 0xE000..=0xF8FF | 0xF0000..=0xFFFFD | 0x100000..=0x10FFFD)
+}
+
+fn is_hex_digit(b: u8) -> bool {
+    (b as char).is_ascii_hexdigit()
+}
+
+fn is_pct_encoded(input: &[u8], position: usize) -> bool {
+    input.len() >= position + 3 && input[position] == b'%' && is_hex_digit(input[position + 1]) && is_hex_digit(input[position + 2])
+}
+
+fn is_varchar(b: u8) -> bool {
+    (b as char).is_ascii_alphanumeric() || b == b'_'
+}
+
+/// Checks that `name` is a valid RFC 6570 varname.
+pub(crate) fn is_valid_varname(name: &str) -> bool {
+    let input = name.as_bytes();
+    let mut position = 0;
+    let mut previous_was_dot = true;
+    while position < input.len() {
+        if is_varchar(input[position]) {
+            position += 1;
+        } else if is_pct_encoded(input, position) {
+            position += 3;
+        } else if input[position] == b'.' && !previous_was_dot {
+            position += 1;
+            previous_was_dot = true;
+            continue;
+        } else {
+            return false;
+        }
+        previous_was_dot = false;
+    }
+    !previous_was_dot
+}
+
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+    options: &'a UriTemplateParseOptions,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str, options: &'a UriTemplateParseOptions) -> Parser<'a> {
+        Parser {
+            input,
+            position: 0,
+            options,
+        }
+    }
+
+    fn exceeds(count: usize, limit: Option<usize>) -> bool {
+        limit.map(|limit| count > limit).unwrap_or(false)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn parse(mut self) -> Result<UriTemplate, UriTemplateParseError> {
+        if let Some(max_length) = self.options.max_length {
+            if self.input.len() > max_length {
+                let mut position = max_length;
+                while !self.input.is_char_boundary(position) {
+                    position -= 1;
+                }
+                return Err(UriTemplateParseError::TemplateTooLong(position));
+            }
+        }
+        let mut components: Vec<UriTemplateComponent> = vec!();
+        let mut expressions = 0;
+        while let Some(c) = self.peek() {
+            if c == '{' {
+                expressions += 1;
+                if Parser::exceeds(expressions, self.options.max_expressions) {
+                    return Err(UriTemplateParseError::TooManyExpressions(self.position));
+                }
+                components.push(self.parse_expression()?);
+            } else {
+                components.push(self.parse_literal()?);
+            }
+        }
+        Ok(UriTemplate::from_components(components))
+    }
+
+    fn parse_literal(&mut self) -> Result<UriTemplateComponent, UriTemplateParseError> {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            if c == '{' {
+                break;
+            }
+            if c == '%' {
+                if !is_pct_encoded(self.input.as_bytes(), self.position) {
+                    return Err(UriTemplateParseError::InvalidPercentEncoding(self.position));
+                }
+                self.position += 3;
+                continue;
+            }
+            if !is_literal_char(c) {
+                return Err(UriTemplateParseError::InvalidLiteralCharacter(self.position, c));
+            }
+            self.position += c.len_utf8();
+        }
+        Ok(UriTemplateComponent::Literal(self.input[start..self.position].to_string()))
+    }
+
+    fn parse_expression(&mut self) -> Result<UriTemplateComponent, UriTemplateParseError> {
+        let start = self.position;
+        self.position += 1;
+
+        let operator = match self.peek() {
+            Some('+') => Some(UriTemplateOperator::ReservedCharacter),
+            Some('#') => Some(UriTemplateOperator::Fragment),
+            Some('.') => Some(UriTemplateOperator::PathExtension),
+            Some('/') => Some(UriTemplateOperator::PathComponent),
+            Some(';') => Some(UriTemplateOperator::PathParameter),
+            Some('?') => Some(UriTemplateOperator::QueryParameter),
+            Some('&') => Some(UriTemplateOperator::QueryContinuation),
+            Some(c @ '=') | Some(c @ ',') | Some(c @ '!') | Some(c @ '@') | Some(c @ '|') => {
+                return Err(UriTemplateParseError::ReservedOperator(self.position, c));
+            },
+            _ => None,
+        };
+        if operator.is_some() {
+            self.position += 1;
+        }
+
+        let mut variables: Vec<UriTemplateVariable> = vec!();
+        loop {
+            if Parser::exceeds(variables.len() + 1, self.options.max_variables_per_expression) {
+                return Err(UriTemplateParseError::TooManyVariables(self.position));
+            }
+            variables.push(self.parse_varspec()?);
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some('}') => {
+                    self.position += 1;
+                    return Ok(UriTemplateComponent::Variable(operator, variables));
+                },
+                Some(c) => return Err(UriTemplateParseError::UnexpectedCharacter(self.position, c)),
+                None => return Err(UriTemplateParseError::UnterminatedExpression(start)),
+            }
+        }
+    }
+
+    fn parse_varspec(&mut self) -> Result<UriTemplateVariable, UriTemplateParseError> {
+        let name = self.parse_varname()?;
+        let mut variable = match self.peek() {
+            Some('*') => {
+                self.position += 1;
+                UriTemplateVariable::new_explode(name)
+            },
+            Some(':') if !self.constraint_follows() => {
+                self.position += 1;
+                let prefix = self.parse_prefix()?;
+                UriTemplateVariable::new_prefix(name, prefix)
+            },
+            _ => UriTemplateVariable::new_simple(name),
+        };
+        if self.constraint_follows() {
+            if self.options.strict {
+                return Err(UriTemplateParseError::UnsupportedExtension(self.position));
+            }
+            // A prefix of a value would not satisfy the constraint the value
+            // does.
+            if variable.prefix().is_some() {
+                return Err(UriTemplateParseError::InvalidConstraint(self.position + 1));
+            }
+            self.position += 1;
+            variable = variable.with_constraint(self.parse_constraint()?);
+        }
+        if self.peek() == Some('=') {
+            if self.options.strict {
+                return Err(UriTemplateParseError::UnsupportedExtension(self.position));
+            }
+            self.position += 1;
+            let start = self.position;
+            let default = self.parse_default()?;
+            if let Some(constraint) = variable.constraint() {
+                if !constraint.is_satisfied_by(&default, |_, _| true) {
+                    return Err(UriTemplateParseError::InvalidDefault(start));
+                }
+            }
+            return Ok(variable.with_default(default));
+        }
+        Ok(variable)
+    }
+
+    // Whether a `:` starting a constraint rather than a prefix is next.
+    fn constraint_follows(&self) -> bool {
+        let mut chars = self.input[self.position..].chars();
+        chars.next() == Some(':') && chars.next().map(|c| c.is_ascii_alphabetic() || c == '_' || c == '(').unwrap_or(false)
+    }
+
+    // constraint = "(" value *( "|" value ) ")" / ( ALPHA / "_" ) *( ALPHA / DIGIT / "_" )
+    fn parse_constraint(&mut self) -> Result<UriTemplateConstraint, UriTemplateParseError> {
+        let start = self.position;
+        if self.peek() == Some('(') {
+            self.position += 1;
+            let mut values: Vec<String> = vec!();
+            loop {
+                let value_start = self.position;
+                while let Some(c) = self.peek() {
+                    if !is_literal_char(c) || c == '(' || c == ')' || c == ',' {
+                        break;
+                    }
+                    self.position += c.len_utf8();
+                }
+                if self.position == value_start {
+                    return Err(UriTemplateParseError::InvalidConstraint(start));
+                }
+                values.push(self.input[value_start..self.position].to_string());
+                match self.peek() {
+                    Some('|') => self.position += 1,
+                    Some(')') => {
+                        self.position += 1;
+                        return Ok(UriTemplateConstraint::OneOf(values));
+                    },
+                    _ => return Err(UriTemplateParseError::InvalidConstraint(start)),
+                }
+            }
+        }
+        let length = self.input[start..].bytes().take_while(|b| is_varchar(*b)).count();
+        self.position += length;
+        Ok(match &self.input[start..self.position] {
+            "int" => UriTemplateConstraint::Int,
+            "uuid" => UriTemplateConstraint::Uuid,
+            name => UriTemplateConstraint::Custom(name.to_string()),
+        })
+    }
+
+    // Parses a default value, the literal characters up to the next `,` or
+    // `}`.
+    fn parse_default(&mut self) -> Result<String, UriTemplateParseError> {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            if c == ',' || c == '}' {
+                break;
+            }
+            if c == '%' {
+                if !is_pct_encoded(self.input.as_bytes(), self.position) {
+                    return Err(UriTemplateParseError::InvalidPercentEncoding(self.position));
+                }
+                self.position += 3;
+                continue;
+            }
+            if !is_literal_char(c) {
+                return Err(UriTemplateParseError::UnexpectedCharacter(self.position, c));
+            }
+            self.position += c.len_utf8();
+        }
+        Ok(self.input[start..self.position].to_string())
+    }
+
+    fn parse_varname(&mut self) -> Result<String, UriTemplateParseError> {
+        let input = self.input.as_bytes();
+        let start = self.position;
+        loop {
+            if self.position < input.len() && is_varchar(input[self.position]) {
+                self.position += 1;
+            } else if self.position < input.len() && input[self.position] == b'%' {
+                if !is_pct_encoded(input, self.position) {
+                    return Err(UriTemplateParseError::InvalidPercentEncoding(self.position));
+                }
+                self.position += 3;
+            } else {
+                break;
+            }
+            if self.position + 1 < input.len() && input[self.position] == b'.' && (is_varchar(input[self.position + 1]) || input[self.position + 1] == b'%') {
+                self.position += 1;
+            }
+        }
+        if self.position == start {
+            return match self.peek() {
+                None => Err(UriTemplateParseError::UnterminatedExpression(start)),
+                Some(c) if c == ',' || c == '}' || c == '*' || c == ':' => Err(UriTemplateParseError::EmptyVariableName(start)),
+                Some(c) => Err(UriTemplateParseError::UnexpectedCharacter(start, c)),
+            };
+        }
+        Ok(self.input[start..self.position].to_string())
+    }
+
+    // max-length = %x31-39 0*3DIGIT ; positive integer < 10000
+    fn parse_prefix(&mut self) -> Result<u32, UriTemplateParseError> {
+        let start = self.position;
+        let digits = self.input[start..].bytes().take_while(|b| b.is_ascii_digit()).count();
+        if digits == 0 || digits > 4 || self.input.as_bytes()[start] == b'0' {
+            return Err(UriTemplateParseError::InvalidPrefix(start));
+        }
+        self.position += digits;
+        Ok(self.input[start..self.position].parse().unwrap())
+    }
+}
+
+
+pub fn parse(input: &str) -> Result<UriTemplate, UriTemplateParseError> {
+    Parser::new(input, &UriTemplateParseOptions::new()).parse()
+}
+
+pub fn parse_with_options(input: &str, options: &UriTemplateParseOptions) -> Result<UriTemplate, UriTemplateParseError> {
+    Parser::new(input, options).parse()
+}
+
+// Parses `input` as a single literal, in which `{` is an invalid character
+// rather than the start of an expression.
+pub(crate) fn parse_literal(input: &str) -> Result<String, UriTemplateParseError> {
+    let options = UriTemplateParseOptions::new();
+    let mut parser = Parser::new(input, &options);
+    let literal = match parser.parse_literal()? {
+        UriTemplateComponent::Literal(literal) => literal,
+        UriTemplateComponent::Variable(_, _) => unreachable!("parse_literal only returns literals"),
+    };
+    match parser.peek() {
+        Some(c) => Err(UriTemplateParseError::InvalidLiteralCharacter(parser.position, c)),
+        None => Ok(literal),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::{UriTemplate, UriTemplateBuilder, UriTemplateParseError, UriTemplateParseOptions};
+    use super::super::{UriTemplateConstraint, UriTemplateOperator, UriTemplateModifier, UriTemplateVariable};
+
+    #[test]
+    fn test_empty() {
+        let t = UriTemplate::parse("").unwrap();
+        assert_eq!(t, UriTemplateBuilder::new().into_uri_template());
+    }
+
+    #[test]
+    fn test_literal() {
+        let t = UriTemplate::parse("http://example.com/%7Efoo").unwrap();
+        assert_eq!(t, UriTemplateBuilder::new().literal("http://example.com/%7Efoo").into_uri_template());
+        assert!(UriTemplate::parse("/\u{E1000}").is_ok());
+        assert_eq!(UriTemplate::parse("/\u{E0001}"), Err(UriTemplateParseError::InvalidLiteralCharacter(1, '\u{E0001}')));
+    }
+
+    #[test]
+    fn test_expressions() {
+        let t: UriTemplate = "http://example.com/{/splat*}{?foo,bar,hash:7}".parse().unwrap();
+        let b = UriTemplateBuilder::new()
+            .literal("http://example.com/")
+            .component(Some(UriTemplateOperator::PathComponent), |c| {
+                c.variable("splat", Some(UriTemplateModifier::Explode))
+            })
+            .component(Some(UriTemplateOperator::QueryParameter), |c| {
+                c.variable("foo", None)
+                 .variable("bar", None)
+                 .variable("hash", Some(UriTemplateModifier::Prefix(7)))
+            })
+            .into_uri_template();
+        assert_eq!(t, b);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let templates = [
+            "{var}", "{+path}/here", "{#x,hello,y}", "X{.list*}", "{/var:1,var}",
+            "{;x,y,empty}", "{?x,y,undef}", "?fixed=yes{&x}", "{var.name,a%20b}",
+        ];
+        for template in templates.iter() {
+            assert_eq!(UriTemplate::parse(template).unwrap().to_template_string(), *template);
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let options = UriTemplateParseOptions::new().max_length(20).max_expressions(2).max_variables_per_expression(2);
+        let parse = |template: &str| UriTemplate::parse_with_options(template, &options);
+        assert!(parse("/a{b}/{c,d}").is_ok());
+        assert_eq!(parse("/aaaaaaaaaaaaaaaaaaaa"), Err(UriTemplateParseError::TemplateTooLong(20)));
+        assert_eq!(parse("/aaaaaaaaaaaaaaaaaaé"), Err(UriTemplateParseError::TemplateTooLong(19)));
+        assert_eq!(parse("/aaaaaaaaaaaaaaaaaaaa").unwrap_err().to_string(), "template exceeds the maximum length at offset 20");
+        assert_eq!(parse("{a}{b}{c}"), Err(UriTemplateParseError::TooManyExpressions(6)));
+        assert_eq!(parse("{a}{?b,c,d}"), Err(UriTemplateParseError::TooManyVariables(9)));
+        assert!(UriTemplate::parse_with_options("{a}{b}{c}", &UriTemplateParseOptions::new()).is_ok());
+    }
+
+    #[test]
+    fn test_defaults() {
+        let options = UriTemplateParseOptions::new().strict(false);
+        let t = UriTemplate::parse_with_options("/search{?q,format=json,hash:3=abc,list*=}{lang=en-GB}", &options).unwrap();
+        let b = UriTemplateBuilder::new()
+            .literal("/search")
+            .component(Some(UriTemplateOperator::QueryParameter), |c| {
+                c.variable("q", None)
+                 .variable_with_default("format", None, "json")
+                 .variable_with_default("hash", Some(UriTemplateModifier::Prefix(3)), "abc")
+                 .variable_with_default("list", Some(UriTemplateModifier::Explode), "")
+            })
+            .component(None, |c| c.variable_with_default("lang", None, "en-GB"))
+            .into_uri_template();
+        assert_eq!(t, b);
+        assert_eq!(t.to_template_string(), "/search{?q,format=json,hash:3=abc,list*=}{lang=en-GB}");
+
+        assert_eq!(UriTemplate::parse("{?format=json}"), Err(UriTemplateParseError::UnsupportedExtension(8)));
+        assert_eq!(UriTemplate::parse_with_options("{x=a b}", &options), Err(UriTemplateParseError::UnexpectedCharacter(4, ' ')));
+        assert_eq!(UriTemplate::parse_with_options("{x=50%}", &options), Err(UriTemplateParseError::InvalidPercentEncoding(5)));
+    }
+
+    #[test]
+    fn test_constraints() {
+        let options = UriTemplateParseOptions::new().strict(false);
+        let t = UriTemplate::parse_with_options("/users/{id:int}{.format:(json|csv)=json}{/path*:slug}{?v:uuid}", &options).unwrap();
+        let b = UriTemplateBuilder::new()
+            .literal("/users/")
+            .component(None, |c| c.variable_with_constraint("id", None, UriTemplateConstraint::Int))
+            .component(Some(UriTemplateOperator::PathExtension), |c| {
+                c.varspec(UriTemplateVariable::new_simple("format".to_string())
+                    .with_constraint(UriTemplateConstraint::OneOf(vec!("json".to_string(), "csv".to_string())))
+                    .with_default("json".to_string()))
+            })
+            .component(Some(UriTemplateOperator::PathComponent), |c| {
+                c.variable_with_constraint("path", Some(UriTemplateModifier::Explode), UriTemplateConstraint::Custom("slug".to_string()))
+            })
+            .component(Some(UriTemplateOperator::QueryParameter), |c| {
+                c.variable_with_constraint("v", None, UriTemplateConstraint::Uuid)
+            })
+            .into_uri_template();
+        assert_eq!(t, b);
+        assert_eq!(t.to_template_string(), "/users/{id:int}{.format:(json|csv)=json}{/path*:slug}{?v:uuid}");
+
+        assert_eq!(UriTemplate::parse("{id:int}"), Err(UriTemplateParseError::UnsupportedExtension(3)));
+        assert_eq!(UriTemplate::parse("{id:}"), Err(UriTemplateParseError::InvalidPrefix(4)));
+        assert_eq!(UriTemplate::parse_with_options("{x:(a|)}", &options), Err(UriTemplateParseError::InvalidConstraint(3)));
+        assert_eq!(UriTemplate::parse_with_options("{x:(a}", &options), Err(UriTemplateParseError::InvalidConstraint(3)));
+        assert_eq!(UriTemplate::parse_with_options("{v:3:uuid}", &options), Err(UriTemplateParseError::InvalidConstraint(5)));
+        assert_eq!(UriTemplate::parse_with_options("{id:int=abc}", &options), Err(UriTemplateParseError::InvalidDefault(8)));
+        assert!(UriTemplate::parse_with_options("{id:int=42,s:slug=a}", &options).is_ok());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(UriTemplate::parse("{var"), Err(UriTemplateParseError::UnterminatedExpression(0)));
+        assert_eq!(UriTemplate::parse("a{}"), Err(UriTemplateParseError::EmptyVariableName(2)));
+        assert_eq!(UriTemplate::parse("{=var}"), Err(UriTemplateParseError::ReservedOperator(1, '=')));
+        assert_eq!(UriTemplate::parse("{var:0}"), Err(UriTemplateParseError::InvalidPrefix(5)));
+        assert_eq!(UriTemplate::parse("{var:10000}"), Err(UriTemplateParseError::InvalidPrefix(5)));
+        assert_eq!(UriTemplate::parse("{var-x}"), Err(UriTemplateParseError::UnexpectedCharacter(4, '-')));
+        assert_eq!(UriTemplate::parse("{a..b}"), Err(UriTemplateParseError::UnexpectedCharacter(2, '.')));
+        assert_eq!(UriTemplate::parse("50%"), Err(UriTemplateParseError::InvalidPercentEncoding(2)));
+        assert_eq!(UriTemplate::parse("a b"), Err(UriTemplateParseError::InvalidLiteralCharacter(1, ' ')));
+        assert_eq!(UriTemplate::parse("a}"), Err(UriTemplateParseError::InvalidLiteralCharacter(1, '}')));
+    }
+
+    #[test]
+    fn test_varnames() {
+        assert!(super::is_valid_varname("foo"));
+        assert!(super::is_valid_varname("foo.bar_2"));
+        assert!(super::is_valid_varname("%20x"));
+        assert!(!super::is_valid_varname(""));
+        assert!(!super::is_valid_varname(".foo"));
+        assert!(!super::is_valid_varname("foo."));
+        assert!(!super::is_valid_varname("foo..bar"));
+        assert!(!super::is_valid_varname("foo-bar"));
+    }
+}
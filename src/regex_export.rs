@@ -0,0 +1,171 @@
+use std::vec::Vec;
+
+use super::types::{UriTemplate, UriTemplateComponent, UriTemplateOperator, UriTemplateVariable};
+
+
+/// Options for `UriTemplate::to_regex_string`.
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub struct UriTemplateRegexOptions {
+    strict: bool,
+}
+
+impl Default for UriTemplateRegexOptions {
+    fn default() -> UriTemplateRegexOptions {
+        UriTemplateRegexOptions::new()
+    }
+}
+
+impl UriTemplateRegexOptions {
+    pub fn new() -> UriTemplateRegexOptions {
+        UriTemplateRegexOptions {
+            strict: false,
+        }
+    }
+
+    /// When strict, prefixed variables match at most their prefix length in
+    /// characters, counting a pct-encoded UTF-8 sequence as one, and
+    /// exploded variables must be a separator-delimited list of items (named
+    /// by the variable for `;`, `?` and `&`). Otherwise both match any run of
+    /// the operator's allowed characters, separators and `=`.
+    pub fn strict(mut self, strict: bool) -> UriTemplateRegexOptions {
+        self.strict = strict;
+        self
+    }
+}
+
+
+const UNRESERVED: &str = r"(?:[A-Za-z0-9\-._~]|%[0-9A-Fa-f]{2})";
+const UNRESERVED_LIST: &str = r"(?:[A-Za-z0-9\-._~,]|%[0-9A-Fa-f]{2})";
+const RESERVED: &str = r"(?:[A-Za-z0-9\-._~:/?#\[\]@!$&'()*+,;=]|%[0-9A-Fa-f]{2})";
+// One character of a prefix: an allowed character, or the pct-encoded
+// UTF-8 sequence of one non-ASCII character.
+const UNRESERVED_CHAR: &str = r"[A-Za-z0-9\-._~]";
+const RESERVED_CHAR: &str = r"[A-Za-z0-9\-._~:/?#\[\]@!$&'()*+,;=]";
+const PCT_ENCODED_CHAR: &str = r"%[0-7][0-9A-Fa-f]|%[C-Dc-d][0-9A-Fa-f]%[89ABab][0-9A-Fa-f]|%[Ee][0-9A-Fa-f](?:%[89ABab][0-9A-Fa-f]){2}|%[Ff][0-7](?:%[89ABab][0-9A-Fa-f]){3}";
+
+fn escape_regex(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        if "\\.+*?()|[]{}^$#".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Capture group names are limited to [A-Za-z0-9_] and must not start with a
+// digit or repeat, so varnames such as `a.b` or `%20` are rewritten.
+fn group_name(name: &str, used: &mut Vec<String>) -> String {
+    let mut base: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    if base.starts_with(|c: char| c.is_ascii_digit()) {
+        base.insert(0, '_');
+    }
+    let mut group = base.clone();
+    let mut n = 2;
+    while used.contains(&group) {
+        group = format!("{}_{}", base, n);
+        n += 1;
+    }
+    used.push(group.clone());
+    group
+}
+
+#[derive(Copy,Clone)]
+pub(crate) struct ExpressionRules {
+    pub(crate) prefix: &'static str,
+    pub(crate) separator: &'static str,
+    pub(crate) named: bool,
+    pub(crate) ifemp: bool,
+    pub(crate) reserved: bool,
+}
+
+pub(crate) fn rules(operator: Option<UriTemplateOperator>) -> ExpressionRules {
+    let (prefix, separator, named, ifemp, reserved) = match operator {
+        None => ("", ",", false, false, false),
+        Some(UriTemplateOperator::ReservedCharacter) => ("", ",", false, false, true),
+        Some(UriTemplateOperator::Fragment) => ("#", ",", false, false, true),
+        Some(UriTemplateOperator::PathExtension) => (".", ".", false, false, false),
+        Some(UriTemplateOperator::PathComponent) => ("/", "/", false, false, false),
+        Some(UriTemplateOperator::PathParameter) => (";", ";", true, false, false),
+        Some(UriTemplateOperator::QueryParameter) => ("?", "&", true, true, false),
+        Some(UriTemplateOperator::QueryContinuation) => ("&", "&", true, true, false),
+    };
+    ExpressionRules { prefix, separator, named, ifemp, reserved }
+}
+
+fn variable_regex(rules: &ExpressionRules, v: &UriTemplateVariable, group: &str, options: &UriTemplateRegexOptions) -> String {
+    let chars = if rules.reserved { RESERVED } else { UNRESERVED };
+    let name = escape_regex(v.name());
+    let separator = escape_regex(rules.separator);
+    if v.explode() {
+        let list = if !options.strict {
+            format!("(?:{}|{}|=|,)*?", chars, separator)
+        } else {
+            let item = if rules.named {
+                format!("{}(?:={}*?)?", name, chars)
+            } else {
+                format!("{}*?(?:={}*?)?", chars, chars)
+            };
+            format!("{}(?:{}{})*", item, separator, item)
+        };
+        return format!("(?P<{}>{})", group, list);
+    }
+    let value = match v.prefix() {
+        Some(prefix) if options.strict => {
+            let allowed = if rules.reserved { RESERVED_CHAR } else { UNRESERVED_CHAR };
+            format!("(?:{}|{}){{0,{}}}?", allowed, PCT_ENCODED_CHAR, prefix)
+        }
+        _ if rules.reserved => format!("{}*?", chars),
+        _ => format!("{}*?", UNRESERVED_LIST),
+    };
+    if !rules.named {
+        format!("(?P<{}>{})", group, value)
+    } else if rules.ifemp {
+        format!("{}=(?P<{}>{})", name, group, value)
+    } else {
+        format!("{}(?:=(?P<{}>{}))?", name, group, value)
+    }
+}
+
+impl UriTemplate {
+    /// Builds an anchored regular expression (in the syntax of the `regex`
+    /// crate) matching expansions of the template, with a named capture
+    /// group per variable holding its still pct-encoded value.
+    ///
+    /// Values are matched lazily, so where the split is ambiguous (as with
+    /// `{/path}{.ext}`, since `.` is unreserved) earlier variables take the
+    /// shortest value that still lets the whole pattern match.
+    ///
+    /// Every variable may be undefined, so each is optional and may be
+    /// introduced by either the operator's prefix or its separator. The
+    /// regular expression does not track which variables matched, so it is
+    /// looser than the template: `{?q,page}` also matches `?q=a?page=2` and
+    /// `&page=2`, which `match_uri` rejects. Group
+    /// names are the varnames with characters outside `[A-Za-z0-9_]`
+    /// replaced by `_`, suffixed with `_2`, `_3`, ... when repeated.
+    pub fn to_regex_string(&self, options: &UriTemplateRegexOptions) -> String {
+        let mut used: Vec<String> = vec!();
+        let mut regex = String::from("^");
+        for component in self.components().iter() {
+            match *component {
+                UriTemplateComponent::Literal(ref value) => regex.push_str(&escape_regex(value)),
+                UriTemplateComponent::Variable(operator, ref variables) => {
+                    let rules = rules(operator);
+                    let lead = if rules.prefix == rules.separator {
+                        escape_regex(rules.prefix)
+                    } else {
+                        format!("(?:{}|{})", escape_regex(rules.prefix), escape_regex(rules.separator))
+                    };
+                    for (i, v) in variables.iter().enumerate() {
+                        let group = group_name(v.name(), &mut used);
+                        let lead = if i == 0 { escape_regex(rules.prefix) } else { lead.clone() };
+                        regex.push_str(&format!("(?:{}{})?", lead, variable_regex(&rules, v, &group, options)));
+                    }
+                },
+            }
+        }
+        regex.push('$');
+        regex
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::vec::Vec;
+
+use super::parsing;
+use super::types::{UriTemplate, UriTemplateComponent, UriTemplateOperator, UriTemplateVariable};
+
+
+#[derive(Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub enum UriTemplateRewriteError {
+    /// The new name is not a valid varname.
+    InvalidVariableName(String),
+    /// The new name is already used by another variable of the template.
+    VariableCollision(String),
+    /// The expression cannot be replaced by a template.
+    UnsupportedSubstitution(String),
+}
+
+impl fmt::Display for UriTemplateRewriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UriTemplateRewriteError::InvalidVariableName(ref name) => write!(f, "{:?} is not a valid template variable name", name),
+            UriTemplateRewriteError::VariableCollision(ref name) => write!(f, "variable {:?} is already used in the template", name),
+            UriTemplateRewriteError::UnsupportedSubstitution(ref component) => write!(f, "{:?} cannot be replaced by a template", component),
+        }
+    }
+}
+
+impl Error for UriTemplateRewriteError {}
+
+
+fn renamed(v: &UriTemplateVariable, name: String) -> UriTemplateVariable {
+    let renamed = match (v.prefix(), v.explode()) {
+        (None, false) => UriTemplateVariable::new_simple(name),
+        (Some(prefix), false) => UriTemplateVariable::new_prefix(name, prefix),
+        (None, true) => UriTemplateVariable::new_explode(name),
+        (Some(prefix), true) => UriTemplateVariable::new_explode_prefix(name, prefix),
+    };
+    let renamed = match v.constraint() {
+        Some(constraint) => renamed.with_constraint(constraint.clone()),
+        None => renamed,
+    };
+    match v.default_value() {
+        Some(default) => renamed.with_default(default.to_string()),
+        None => renamed,
+    }
+}
+
+fn variable_names(template: &UriTemplate) -> Vec<&str> {
+    let mut names: Vec<&str> = vec!();
+    for component in template.components().iter() {
+        if let UriTemplateComponent::Variable(_, ref variables) = *component {
+            for v in variables.iter() {
+                if !names.contains(&v.name()) {
+                    names.push(v.name());
+                }
+            }
+        }
+    }
+    names
+}
+
+fn push_component(components: &mut Vec<UriTemplateComponent>, component: UriTemplateComponent) {
+    match (components.last_mut(), component) {
+        (Some(UriTemplateComponent::Literal(previous)), UriTemplateComponent::Literal(value)) => previous.push_str(&value),
+        (_, UriTemplateComponent::Literal(ref value)) if value.is_empty() => {},
+        (_, component) => components.push(component),
+    }
+}
+
+impl UriTemplate {
+    /// Renames every occurrence of the variable `from` to `to`, keeping
+    /// their modifiers. Renaming a variable the template does not use is
+    /// not an error.
+    ///
+    /// Fails, leaving the template unchanged, if `to` is not a valid varname
+    /// or is already used by the template.
+    pub fn rename_variable(&mut self, from: &str, to: &str) -> Result<(), UriTemplateRewriteError> {
+        self.map_variables(|name| if name == from { to.to_string() } else { name.to_string() })
+    }
+
+    /// Renames every variable of the template to the result of calling `f`
+    /// with its name. `f` is called once per distinct name.
+    ///
+    /// Fails, leaving the template unchanged, if a new name is not a valid
+    /// varname or two different variables would end up with the same name.
+    pub fn map_variables<F: FnMut(&str) -> String>(&mut self, mut f: F) -> Result<(), UriTemplateRewriteError> {
+        let mut names: BTreeMap<String, String> = BTreeMap::new();
+        let mut new_names: BTreeMap<String, String> = BTreeMap::new();
+        for name in variable_names(self) {
+            let new_name = f(name);
+            if !parsing::is_valid_varname(&new_name) {
+                return Err(UriTemplateRewriteError::InvalidVariableName(new_name));
+            }
+            if new_names.insert(new_name.clone(), name.to_string()).is_some() {
+                return Err(UriTemplateRewriteError::VariableCollision(new_name));
+            }
+            names.insert(name.to_string(), new_name);
+        }
+        let components = self.components().iter().map(|component| match *component {
+            UriTemplateComponent::Literal(ref value) => UriTemplateComponent::Literal(value.clone()),
+            UriTemplateComponent::Variable(operator, ref variables) => {
+                UriTemplateComponent::Variable(operator, variables.iter().map(|v| renamed(v, names[v.name()].clone())).collect())
+            },
+        }).collect();
+        *self = UriTemplate::from_components(components);
+        Ok(())
+    }
+
+    /// Replaces every expression consisting of just the variable `name` by
+    /// `replacement`, preceded by the operator's prefix, so that `{/rest}`
+    /// with `users/{id}` becomes `/users/{id}`. The replacement is always
+    /// expanded in full, even where the variable would have been undefined.
+    ///
+    /// Only expressions with a single, unmodified variable and an operator
+    /// that does not name the variable (none, `+`, `#`, `.` and `/`) can be
+    /// replaced. Fails, leaving the template unchanged, if any expression
+    /// using `name` cannot be replaced or if `replacement` uses a variable
+    /// the template already uses.
+    pub fn substitute(&mut self, name: &str, replacement: &UriTemplate) -> Result<(), UriTemplateRewriteError> {
+        let existing = variable_names(self);
+        for replacement_name in variable_names(replacement) {
+            if replacement_name != name && existing.contains(&replacement_name) {
+                return Err(UriTemplateRewriteError::VariableCollision(replacement_name.to_string()));
+            }
+        }
+        let mut components: Vec<UriTemplateComponent> = vec!();
+        for component in self.components().iter() {
+            match *component {
+                UriTemplateComponent::Variable(operator, ref variables) if variables.iter().any(|v| v.name() == name) => {
+                    let v = &variables[0];
+                    let prefix = match operator {
+                        None | Some(UriTemplateOperator::ReservedCharacter) => "",
+                        Some(UriTemplateOperator::Fragment) => "#",
+                        Some(UriTemplateOperator::PathExtension) => ".",
+                        Some(UriTemplateOperator::PathComponent) => "/",
+                        _ => return Err(UriTemplateRewriteError::UnsupportedSubstitution(component.to_template_string())),
+                    };
+                    if variables.len() != 1 || v.prefix().is_some() || v.explode() {
+                        return Err(UriTemplateRewriteError::UnsupportedSubstitution(component.to_template_string()));
+                    }
+                    push_component(&mut components, UriTemplateComponent::Literal(prefix.to_string()));
+                    for replacement_component in replacement.components().iter() {
+                        push_component(&mut components, replacement_component.clone());
+                    }
+                },
+                ref component => push_component(&mut components, component.clone()),
+            }
+        }
+        *self = UriTemplate::from_components(components);
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::UriTemplate;
+    use super::UriTemplateRewriteError;
+
+    #[test]
+    fn test_rename_variable() {
+        let mut t = UriTemplate::parse("/users/{id}{?fields*,id:3}").unwrap();
+        t.rename_variable("id", "user_id").unwrap();
+        assert_eq!(t.to_template_string(), "/users/{user_id}{?fields*,user_id:3}");
+        t.rename_variable("missing", "other").unwrap();
+        assert_eq!(t.to_template_string(), "/users/{user_id}{?fields*,user_id:3}");
+        t.rename_variable("fields", "fields").unwrap();
+    }
+
+    #[test]
+    fn test_rename_variable_errors() {
+        let mut t = UriTemplate::parse("/users/{id}{?fields}").unwrap();
+        assert_eq!(t.rename_variable("id", "user-id"), Err(UriTemplateRewriteError::InvalidVariableName("user-id".to_string())));
+        assert_eq!(t.rename_variable("id", "fields"), Err(UriTemplateRewriteError::VariableCollision("fields".to_string())));
+        assert_eq!(t.to_template_string(), "/users/{id}{?fields}");
+    }
+
+    #[test]
+    fn test_map_variables() {
+        let mut t = UriTemplate::parse("{a}{/b,c}").unwrap();
+        t.map_variables(|name| format!("x.{}", name)).unwrap();
+        assert_eq!(t.to_template_string(), "{x.a}{/x.b,x.c}");
+
+        let mut calls = 0;
+        t.map_variables(|name| { calls += 1; name.to_string() }).unwrap();
+        assert_eq!(calls, 3);
+
+        assert_eq!(t.map_variables(|_| "same".to_string()), Err(UriTemplateRewriteError::VariableCollision("same".to_string())));
+        assert_eq!(t.to_template_string(), "{x.a}{/x.b,x.c}");
+    }
+
+    #[test]
+    fn test_substitute() {
+        let mut t = UriTemplate::parse("{+base}/api{/resource}{?q}").unwrap();
+        t.substitute("resource", &UriTemplate::parse("users/{id}").unwrap()).unwrap();
+        assert_eq!(t.to_template_string(), "{+base}/api/users/{id}{?q}");
+        t.substitute("base", &UriTemplate::parse("http://example.com").unwrap()).unwrap();
+        assert_eq!(t.to_template_string(), "http://example.com/api/users/{id}{?q}");
+        assert_eq!(t.components().len(), 3);
+    }
+
+    #[test]
+    fn test_substitute_errors() {
+        let mut t = UriTemplate::parse("{/resource}{?q}").unwrap();
+        assert_eq!(t.substitute("resource", &UriTemplate::parse("{q}").unwrap()),
+            Err(UriTemplateRewriteError::VariableCollision("q".to_string())));
+        assert_eq!(t.substitute("q", &UriTemplate::parse("x").unwrap()),
+            Err(UriTemplateRewriteError::UnsupportedSubstitution("{?q}".to_string())));
+        let mut t = UriTemplate::parse("{a,b}").unwrap();
+        assert_eq!(t.substitute("b", &UriTemplate::parse("x").unwrap()),
+            Err(UriTemplateRewriteError::UnsupportedSubstitution("{a,b}".to_string())));
+        assert_eq!(t.to_template_string(), "{a,b}");
+    }
+}
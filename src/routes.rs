@@ -0,0 +1,243 @@
+use std::error::Error;
+use std::fmt;
+use std::vec::Vec;
+
+use super::parsing::{self, UriTemplateParseError};
+use super::types::{UriTemplate, UriTemplateComponent, UriTemplateOperator, UriTemplateVariable};
+
+
+#[derive(Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub enum RoutePatternError {
+    /// A `:` or `*` not followed by a parameter name.
+    MissingParameterName(usize),
+    /// A `(` without a matching `)`, or a `)` without a matching `(`.
+    UnbalancedGroup(usize),
+    /// An optional group other than `(.:name)` or `(/:name)`.
+    UnsupportedGroup(usize),
+    /// An optional parameter `:name?` not right after a `/`, or `*name?`.
+    UnsupportedOptional(usize),
+    /// Literal text that is not valid in a URI template.
+    InvalidLiteral(usize, UriTemplateParseError),
+    /// A template component with no route pattern equivalent.
+    UnsupportedComponent(String),
+}
+
+impl fmt::Display for RoutePatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RoutePatternError::MissingParameterName(position) => write!(f, "missing parameter name at offset {}", position),
+            RoutePatternError::UnbalancedGroup(position) => write!(f, "unbalanced optional group at offset {}", position),
+            RoutePatternError::UnsupportedGroup(position) => write!(f, "unsupported optional group at offset {}", position),
+            RoutePatternError::UnsupportedOptional(position) => write!(f, "unsupported optional parameter at offset {}", position),
+            RoutePatternError::InvalidLiteral(position, ref e) => write!(f, "invalid literal at offset {}: {}", position, e),
+            RoutePatternError::UnsupportedComponent(ref component) => write!(f, "{:?} cannot be represented as a route pattern", component),
+        }
+    }
+}
+
+impl Error for RoutePatternError {}
+
+
+fn is_name_char(b: u8) -> bool {
+    (b as char).is_ascii_alphanumeric() || b == b'_'
+}
+
+fn name_len(input: &[u8]) -> usize {
+    input.iter().take_while(|b| is_name_char(**b)).count()
+}
+
+fn push_literal(components: &mut Vec<UriTemplateComponent>, pattern: &str, start: usize, end: usize) -> Result<(), RoutePatternError> {
+    if start == end {
+        return Ok(());
+    }
+    let literal = parsing::parse_literal(&pattern[start..end]).map_err(|e| RoutePatternError::InvalidLiteral(start, e))?;
+    components.push(UriTemplateComponent::Literal(literal));
+    Ok(())
+}
+
+impl UriTemplate {
+    /// Converts an Express/Rails-style route pattern into a template.
+    ///
+    /// `:name` becomes `{name}`, `*name` becomes `{+name}`, and the optional
+    /// groups `(.:name)` and `(/:name)` become `{.name}` and `{/name}`, as
+    /// does the optional parameter `/:name?`.
+    pub fn from_route_pattern(pattern: &str) -> Result<UriTemplate, RoutePatternError> {
+        let input = pattern.as_bytes();
+        let mut components: Vec<UriTemplateComponent> = vec!();
+        let mut literal_start = 0;
+        let mut position = 0;
+        while position < input.len() {
+            match input[position] {
+                b':' | b'*' => {
+                    let len = name_len(&input[position + 1..]);
+                    if len == 0 {
+                        return Err(RoutePatternError::MissingParameterName(position));
+                    }
+                    let name = pattern[position + 1..position + 1 + len].to_string();
+                    let mut operator = if input[position] == b'*' { Some(UriTemplateOperator::ReservedCharacter) } else { None };
+                    let mut end = position + 1 + len;
+                    let mut literal_end = position;
+                    // A `?` would start the query if kept as a literal.
+                    if input.get(end) == Some(&b'?') {
+                        if operator.is_some() || position == literal_start || input[position - 1] != b'/' {
+                            return Err(RoutePatternError::UnsupportedOptional(end));
+                        }
+                        operator = Some(UriTemplateOperator::PathComponent);
+                        literal_end -= 1;
+                        end += 1;
+                    }
+                    push_literal(&mut components, pattern, literal_start, literal_end)?;
+                    components.push(UriTemplateComponent::Variable(operator, vec!(UriTemplateVariable::new_simple(name))));
+                    position = end;
+                    literal_start = position;
+                },
+                b'(' => {
+                    let end = match pattern[position..].find(')') {
+                        Some(end) => position + end,
+                        None => return Err(RoutePatternError::UnbalancedGroup(position)),
+                    };
+                    let group = &input[position + 1..end];
+                    let operator = match group.first() {
+                        Some(&b'.') => UriTemplateOperator::PathExtension,
+                        Some(&b'/') => UriTemplateOperator::PathComponent,
+                        _ => return Err(RoutePatternError::UnsupportedGroup(position)),
+                    };
+                    if group.len() < 3 || group[1] != b':' || name_len(&group[2..]) != group.len() - 2 {
+                        return Err(RoutePatternError::UnsupportedGroup(position));
+                    }
+                    push_literal(&mut components, pattern, literal_start, position)?;
+                    let name = pattern[position + 3..end].to_string();
+                    components.push(UriTemplateComponent::Variable(Some(operator), vec!(UriTemplateVariable::new_simple(name))));
+                    position = end + 1;
+                    literal_start = position;
+                },
+                b')' => return Err(RoutePatternError::UnbalancedGroup(position)),
+                _ => position += 1,
+            }
+        }
+        push_literal(&mut components, pattern, literal_start, position)?;
+        Ok(UriTemplate::from_components(components))
+    }
+
+    /// Converts the template into a route pattern, the inverse of
+    /// `from_route_pattern`. Variables with a modifier, a constraint or a
+    /// default value have no route pattern equivalent.
+    pub fn to_route_pattern(&self) -> Result<String, RoutePatternError> {
+        let mut pattern = String::new();
+        let mut after_parameter = false;
+        for component in self.components().iter() {
+            let unsupported = || RoutePatternError::UnsupportedComponent(component.to_template_string());
+            match *component {
+                UriTemplateComponent::Literal(ref value) => {
+                    let continues_name = after_parameter && value.bytes().next().map(is_name_char).unwrap_or(false);
+                    if continues_name || value.contains(&[':', '*', '(', ')'][..]) {
+                        return Err(unsupported());
+                    }
+                    pattern.push_str(value);
+                    after_parameter = false;
+                },
+                UriTemplateComponent::Variable(operator, ref variables) => {
+                    if variables.len() != 1 {
+                        return Err(unsupported());
+                    }
+                    let v = &variables[0];
+                    if v.prefix().is_some() || v.explode() || v.constraint().is_some() || v.default_value().is_some() ||
+                        name_len(v.name().as_bytes()) != v.name().len() {
+                        return Err(unsupported());
+                    }
+                    match operator {
+                        None => pattern.push_str(&format!(":{}", v.name())),
+                        Some(UriTemplateOperator::ReservedCharacter) => pattern.push_str(&format!("*{}", v.name())),
+                        Some(UriTemplateOperator::PathExtension) => pattern.push_str(&format!("(.:{})", v.name())),
+                        Some(UriTemplateOperator::PathComponent) => pattern.push_str(&format!("(/:{})", v.name())),
+                        _ => return Err(unsupported()),
+                    }
+                    after_parameter = operator.is_none() || operator == Some(UriTemplateOperator::ReservedCharacter);
+                },
+            }
+        }
+        Ok(pattern)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::{UriTemplate, UriTemplateValues, UriTemplateParseError, UriTemplateParseOptions};
+    use super::RoutePatternError;
+
+    #[test]
+    fn test_from_route_pattern() {
+        let t = UriTemplate::from_route_pattern("/users/:id/posts/:postId(.:format)").unwrap();
+        assert_eq!(t.to_template_string(), "/users/{id}/posts/{postId}{.format}");
+
+        let t = UriTemplate::from_route_pattern("/files/*path(/:page)").unwrap();
+        assert_eq!(t.to_template_string(), "/files/{+path}{/page}");
+
+        let mut v = UriTemplateValues::new();
+        v.set("path", "a/b c");
+        assert_eq!(t.to_string_with_values(&v), "/files/a/b%20c");
+    }
+
+    #[test]
+    fn test_from_route_pattern_optional() {
+        let t = UriTemplate::from_route_pattern("/users/:id?").unwrap();
+        assert_eq!(t.to_template_string(), "/users{/id}");
+        assert_eq!(t.to_string_with_values(&UriTemplateValues::new()), "/users");
+        assert!(t.match_uri("/users").is_some());
+        assert!(t.match_uri("/users/42").is_some());
+
+        let t = UriTemplate::from_route_pattern("/:lang?/docs").unwrap();
+        assert_eq!(t.to_template_string(), "{/lang}/docs");
+    }
+
+    #[test]
+    fn test_from_route_pattern_errors() {
+        assert_eq!(UriTemplate::from_route_pattern("/users/:"), Err(RoutePatternError::MissingParameterName(7)));
+        assert_eq!(UriTemplate::from_route_pattern("/files/*"), Err(RoutePatternError::MissingParameterName(7)));
+        assert_eq!(UriTemplate::from_route_pattern("/users(/:id"), Err(RoutePatternError::UnbalancedGroup(6)));
+        assert_eq!(UriTemplate::from_route_pattern("/users/:id)"), Err(RoutePatternError::UnbalancedGroup(10)));
+        assert_eq!(UriTemplate::from_route_pattern("/users(/admin)"), Err(RoutePatternError::UnsupportedGroup(6)));
+        assert_eq!(UriTemplate::from_route_pattern("/users(-:id)"), Err(RoutePatternError::UnsupportedGroup(6)));
+        assert_eq!(UriTemplate::from_route_pattern("/users-:id?"), Err(RoutePatternError::UnsupportedOptional(10)));
+        assert_eq!(UriTemplate::from_route_pattern(":id?"), Err(RoutePatternError::UnsupportedOptional(3)));
+        assert_eq!(UriTemplate::from_route_pattern("/files/*path?"), Err(RoutePatternError::UnsupportedOptional(12)));
+        assert_eq!(UriTemplate::from_route_pattern("/users/:id/a b"),
+            Err(RoutePatternError::InvalidLiteral(10, UriTemplateParseError::InvalidLiteralCharacter(2, ' '))));
+        assert_eq!(UriTemplate::from_route_pattern("/users/{id}"),
+            Err(RoutePatternError::InvalidLiteral(0, UriTemplateParseError::InvalidLiteralCharacter(7, '{'))));
+        assert_eq!(UriTemplate::from_route_pattern("/:id{foo}"),
+            Err(RoutePatternError::InvalidLiteral(4, UriTemplateParseError::InvalidLiteralCharacter(0, '{'))));
+        assert_eq!(UriTemplate::from_route_pattern("/a%2"),
+            Err(RoutePatternError::InvalidLiteral(0, UriTemplateParseError::InvalidPercentEncoding(2))));
+    }
+
+    #[test]
+    fn test_to_route_pattern() {
+        let pattern = "/users/:id/posts/:postId(.:format)";
+        let t = UriTemplate::parse("/users/{id}/posts/{postId}{.format}").unwrap();
+        assert_eq!(t.to_route_pattern().unwrap(), pattern);
+        assert_eq!(UriTemplate::from_route_pattern(pattern).unwrap(), t);
+
+        let t = UriTemplate::parse("/files/{+path}{/page}").unwrap();
+        assert_eq!(t.to_route_pattern().unwrap(), "/files/*path(/:page)");
+    }
+
+    #[test]
+    fn test_to_route_pattern_errors() {
+        let unsupported = |template: &str, component: &str| {
+            let options = UriTemplateParseOptions::new().strict(false);
+            assert_eq!(UriTemplate::parse_with_options(template, &options).unwrap().to_route_pattern(),
+                Err(RoutePatternError::UnsupportedComponent(component.to_string())));
+        };
+        unsupported("/users/{id}{?fields}", "{?fields}");
+        unsupported("/users/{a,b}", "{a,b}");
+        unsupported("/users/{id*}", "{id*}");
+        unsupported("/users/{id:3}", "{id:3}");
+        unsupported("/users/{id:int=5}", "{id:int=5}");
+        unsupported("/users/{id=5}", "{id=5}");
+        unsupported("/users/{user.id}", "{user.id}");
+        unsupported("/users/{id}x", "x");
+        unsupported("/users:batch", "/users:batch");
+    }
+}
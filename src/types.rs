@@ -1,15 +1,28 @@
 extern crate std;
 
 use std::vec::Vec;
+use std::borrow::Cow;
+use std::cmp;
+#[cfg(feature = "http")]
+use std::collections::btree_map;
 use std::collections::{
+    BTreeMap,
+    BTreeSet,
     HashMap,
-    HashSet,
 };
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::slice;
+use std::str::FromStr;
 
 use building;
+use constraints::UriTemplateConstraint;
+use escaping::{self, Sink};
+use expanding;
+use parsing;
 
 
-#[derive(Copy,Clone,PartialEq,Eq)]
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
 pub enum UriTemplateOperator {
     ReservedCharacter,
     Fragment,
@@ -21,107 +34,164 @@ pub enum UriTemplateOperator {
 }
 
 
-#[derive(Clone,PartialEq,Eq)]
+/// Displays as the operator character, e.g. `?` for `QueryParameter`.
+impl fmt::Display for UriTemplateOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match *self {
+            UriTemplateOperator::ReservedCharacter => "+",
+            UriTemplateOperator::Fragment => "#",
+            UriTemplateOperator::PathExtension => ".",
+            UriTemplateOperator::PathComponent => "/",
+            UriTemplateOperator::PathParameter => ";",
+            UriTemplateOperator::QueryParameter => "?",
+            UriTemplateOperator::QueryContinuation => "&",
+        })
+    }
+}
+
+
+#[derive(Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
 pub struct UriTemplateVariable {
     name: String,
     explode: bool,
     prefix: Option<u32>,
+    constraint: Option<UriTemplateConstraint>,
+    default: Option<String>,
 }
 
 
 impl UriTemplateVariable {
     pub fn new_simple(name: String) -> UriTemplateVariable {
-        UriTemplateVariable{ name: name, prefix: None, explode: false }
+        UriTemplateVariable{ name, prefix: None, explode: false, constraint: None, default: None }
     }
 
     pub fn new_prefix(name: String, prefix: u32) -> UriTemplateVariable {
-        UriTemplateVariable{ name: name, prefix: Some(prefix), explode: false }
+        UriTemplateVariable{ name, prefix: Some(prefix), explode: false, constraint: None, default: None }
     }
 
     pub fn new_explode(name: String) -> UriTemplateVariable {
-        UriTemplateVariable{ name: name, prefix: None, explode: true }
+        UriTemplateVariable{ name, prefix: None, explode: true, constraint: None, default: None }
     }
 
     pub fn new_explode_prefix(name: String, prefix: u32) -> UriTemplateVariable {
-        UriTemplateVariable{ name: name, prefix: Some(prefix), explode: true }
+        UriTemplateVariable{ name, prefix: Some(prefix), explode: true, constraint: None, default: None }
+    }
+
+    /// Sets the constraint on the variable's values, written `{name:int}`,
+    /// see `UriTemplateConstraint`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the variable has a prefix modifier, or a default value that
+    /// does not satisfy the constraint. Such templates do not parse, as
+    /// `match_uri` would check the constraint on the prefixed value.
+    pub fn with_constraint(mut self, constraint: UriTemplateConstraint) -> UriTemplateVariable {
+        assert!(self.prefix.is_none(), "constraint on {:?}, which has a prefix modifier", self.name);
+        self.constraint = Some(constraint);
+        assert!(self.default_satisfies_constraint(), "default value of {:?} does not satisfy its constraint", self.name);
+        self
+    }
+
+    /// Sets the value expanded when the variable is undefined, written
+    /// `{name=default}`. This is an extension to RFC 6570, which templates
+    /// only accept when parsed with `UriTemplateParseOptions::strict(false)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the default value does not satisfy the variable's
+    /// constraint. `Custom` constraints are not checked.
+    pub fn with_default(mut self, default: String) -> UriTemplateVariable {
+        self.default = Some(default);
+        assert!(self.default_satisfies_constraint(), "default value of {:?} does not satisfy its constraint", self.name);
+        self
+    }
+
+    // Whether the default value, if any, satisfies the constraint, if any.
+    // `Custom` constraints are taken to be satisfied.
+    pub(crate) fn default_satisfies_constraint(&self) -> bool {
+        match (&self.constraint, &self.default) {
+            (Some(constraint), Some(default)) => constraint.is_satisfied_by(default, |_, _| true),
+            _ => true,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn prefix(&self) -> Option<u32> {
+        self.prefix
+    }
+
+    pub fn explode(&self) -> bool {
+        self.explode
+    }
+
+    pub fn constraint(&self) -> Option<&UriTemplateConstraint> {
+        self.constraint.as_ref()
+    }
+
+    pub fn default_value(&self) -> Option<&str> {
+        self.default.as_deref()
     }
 
     pub fn into_template_string(self) -> String {
+        if self.constraint.is_some() || self.default.is_some() {
+            return self.to_template_string();
+        }
         match self {
-            UriTemplateVariable{ name, prefix: None, explode: false } => name,
-            UriTemplateVariable{ name, prefix: Some(prefix), explode: false } => format!("{}:{}", name, prefix),
-            UriTemplateVariable{ name, prefix: None, explode: true } => format!("{}*", name),
-            UriTemplateVariable{ name, prefix: Some(prefix), explode: true } => format!("{}*:{}", name, prefix),
+            UriTemplateVariable{ name, prefix: None, explode: false, .. } => name,
+            UriTemplateVariable{ name, prefix: Some(prefix), explode: false, .. } => format!("{}:{}", name, prefix),
+            UriTemplateVariable{ name, prefix: None, explode: true, .. } => format!("{}*", name),
+            UriTemplateVariable{ name, prefix: Some(prefix), explode: true, .. } => format!("{}*:{}", name, prefix),
         }
     }
 
     pub fn to_template_string(&self) -> String {
-        match self {
-            &UriTemplateVariable{ ref name, prefix: None, explode: false } => name.clone(),
-            &UriTemplateVariable{ ref name, prefix: Some(prefix), explode: false } => format!("{}:{}", name, prefix),
-            &UriTemplateVariable{ ref name, prefix: None, explode: true } => format!("{}*", name),
-            &UriTemplateVariable{ ref name, prefix: Some(prefix), explode: true } => format!("{}*:{}", name, prefix),
+        let mut varspec = match *self {
+            UriTemplateVariable{ ref name, prefix: None, explode: false, .. } => name.clone(),
+            UriTemplateVariable{ ref name, prefix: Some(prefix), explode: false, .. } => format!("{}:{}", name, prefix),
+            UriTemplateVariable{ ref name, prefix: None, explode: true, .. } => format!("{}*", name),
+            UriTemplateVariable{ ref name, prefix: Some(prefix), explode: true, .. } => format!("{}*:{}", name, prefix),
+        };
+        if let Some(ref constraint) = self.constraint {
+            varspec = format!("{}:{}", varspec, constraint);
         }
+        match self.default {
+            Some(ref default) => format!("{}={}", varspec, default),
+            None => varspec,
+        }
+    }
+}
+
+
+/// Displays as the varspec, e.g. `hash:7`.
+impl fmt::Display for UriTemplateVariable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&self.to_template_string())
     }
 }
 
 
-#[derive(Clone,PartialEq,Eq)]
+#[derive(Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
 pub enum UriTemplateComponent {
     Literal(String),
     Variable(Option<UriTemplateOperator>, Vec<UriTemplateVariable>),
 }
 
-#[derive(Copy,Clone,PartialEq,Eq)]
-enum UriTemplateEscaping {
-    U,
-    UR,
-}
-
-
-fn escape_string(method: UriTemplateEscaping, input: &str) -> String {
-    let str_u = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-._~";
-    let str_ur = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-._~:/?#[]@!$&'()*+,;=";
-
-    let mut set_u: HashSet<u8> = HashSet::new();
-    for b in str_u.as_bytes().iter() {
-        set_u.insert(*b);
-    }
-    let set_u = set_u;
-
-    let mut set_ur: HashSet<u8> = HashSet::new();
-    for b in str_ur.as_bytes().iter() {
-        set_ur.insert(*b);
+/// Displays as the literal text or the `{...}` expression.
+impl fmt::Display for UriTemplateComponent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&self.to_template_string())
     }
-    let set_ur = set_ur;
-
-    let mut s = String::new();
-    for byte in input.as_bytes().iter() {
-        match method {
-            UriTemplateEscaping::U => {
-                if set_u.contains(&(*byte)) {
-                    s.push(*byte as char);
-                } else {
-                    s.push_str(&(format!("%{:02X}", *byte)))
-                }
-            }
-            UriTemplateEscaping::UR => {
-                if set_ur.contains(&(*byte)) {
-                    s.push(*byte as char);
-                } else {
-                    s.push_str(&(format!("%{:02X}", *byte)))
-                }
-            }
-        }
-    }
-    s
 }
 
+
 impl UriTemplateComponent {
     pub fn to_template_string(&self) -> String {
-        match self {
-            &UriTemplateComponent::Literal(ref value) => value.clone(),
-            &UriTemplateComponent::Variable(operator, ref variables) => {
+        match *self {
+            UriTemplateComponent::Literal(ref value) => value.clone(),
+            UriTemplateComponent::Variable(operator, ref variables) => {
                 let varspecs: Vec<String> = variables.iter().map(|v|
                     v.to_template_string()
                 ).collect();
@@ -166,136 +236,270 @@ impl UriTemplateComponent {
         }
     }
 
-    fn strings_apply_prefix(strings: Vec<String>, prefix_len: u32) -> Vec<String> {
-        if strings.len() == 0 {
-            return strings;
+    fn apply_prefix(string: &str, prefix: Option<u32>) -> &str {
+        match prefix.and_then(|prefix| string.char_indices().nth(prefix as usize)) {
+            Some((end, _)) => &string[..end],
+            None => string,
         }
-        strings.into_iter().map(|s| {
-            s.chars().take(prefix_len as usize).collect()
-        }).collect()
     }
 
-    fn strings_apply_escaping(strings: Vec<String>, escaping: UriTemplateEscaping) -> Vec<String> {
-        if strings.len() == 0 {
-            return strings;
+    fn expand_items<S: Sink>(variable: &UriTemplateVariable, items: &[String], escaper: escaping::Escaper, rules: &OperatorRules, path_check: Option<bool>, out: &mut expanding::ExpansionWriter<S>) -> Result<(), expanding::UriTemplateExpansionError> {
+        if rules.include_name && !variable.explode {
+            out.push_str(&variable.name);
+            let empty = items.len() == 1 && UriTemplateComponent::apply_prefix(&items[0], variable.prefix).is_empty();
+            if !empty || rules.include_equals_when_empty {
+                out.push_str("=");
+            }
+        }
+        for (i, item) in items.iter().enumerate() {
+            let item = UriTemplateComponent::apply_prefix(item, variable.prefix);
+            if i > 0 {
+                out.push_str(if variable.explode { rules.separator } else { "," });
+            }
+            if rules.include_name && variable.explode {
+                out.push_str(&variable.name);
+                out.push_str("=");
+            }
+            if let Some(reserved) = path_check {
+                expanding::check_path_item(&variable.name, item, reserved)?;
+            }
+            escaper.escape(item, out);
+            out.check_length()?;
         }
-        strings.into_iter().map(|s|
-            escape_string(escaping, s.as_ref())
-        ).collect()
+        Ok(())
     }
 
-    pub fn to_string_with_values(&self, values: &UriTemplateValues) -> String {
-        match self {
-            &UriTemplateComponent::Literal(ref value) => escape_string(UriTemplateEscaping::UR, value.as_ref()),
-            &UriTemplateComponent::Variable(operator, ref variables) => {
-                let prefix: &'static str = operator.map(|o|
-                    match o {
-                        UriTemplateOperator::ReservedCharacter => "",
-                        UriTemplateOperator::Fragment => "#",
-                        UriTemplateOperator::PathExtension => ".",
-                        UriTemplateOperator::PathComponent => "/",
-                        UriTemplateOperator::PathParameter => ";",
-                        UriTemplateOperator::QueryParameter => "?",
-                        UriTemplateOperator::QueryContinuation => "&",
-                    }
-                ).unwrap_or("");
-
-                let separator: &'static str = operator.map(|o|
-                    match o {
-                        UriTemplateOperator::ReservedCharacter => ",",
-                        UriTemplateOperator::Fragment => ",",
-                        UriTemplateOperator::PathExtension => ".",
-                        UriTemplateOperator::PathComponent => "/",
-                        UriTemplateOperator::PathParameter => ";",
-                        UriTemplateOperator::QueryParameter => "&",
-                        UriTemplateOperator::QueryContinuation => "&",
-                    }
-                ).unwrap_or(",");
-
-                let escaping = operator.map(|o|
-                    match o {
-                        UriTemplateOperator::ReservedCharacter => UriTemplateEscaping::UR,
-                        UriTemplateOperator::Fragment => UriTemplateEscaping::UR,
-                        UriTemplateOperator::PathExtension => UriTemplateEscaping::U,
-                        UriTemplateOperator::PathComponent => UriTemplateEscaping::U,
-                        UriTemplateOperator::PathParameter => UriTemplateEscaping::U,
-                        UriTemplateOperator::QueryParameter => UriTemplateEscaping::U,
-                        UriTemplateOperator::QueryContinuation => UriTemplateEscaping::U,
-                    }
-                ).unwrap_or(UriTemplateEscaping::U);
+    // Prefix modifiers do not apply to associative arrays (RFC 6570 section 2.4.1).
+    fn expand_pairs<S: Sink>(variable: &UriTemplateVariable, pairs: &[(String, String)], escaper: escaping::Escaper, rules: &OperatorRules, out: &mut expanding::ExpansionWriter<S>) -> Result<(), expanding::UriTemplateExpansionError> {
+        if rules.include_name && !variable.explode {
+            out.push_str(&variable.name);
+            out.push_str("=");
+        }
+        for (i, (key, value)) in pairs.iter().enumerate() {
+            if i > 0 {
+                out.push_str(if variable.explode { rules.separator } else { "," });
+            }
+            escaper.escape(key, out);
+            if !variable.explode {
+                out.push_str(",");
+                escaper.escape(value, out);
+            } else if !rules.include_name || !value.is_empty() || rules.include_equals_when_empty {
+                out.push_str("=");
+                escaper.escape(value, out);
+            }
+            out.check_length()?;
+        }
+        Ok(())
+    }
 
-                let include_name: bool = operator.map(|o|
-                    match o {
-                        UriTemplateOperator::ReservedCharacter => false,
-                        UriTemplateOperator::Fragment => false,
-                        UriTemplateOperator::PathExtension => false,
-                        UriTemplateOperator::PathComponent => false,
-                        UriTemplateOperator::PathParameter => true,
-                        UriTemplateOperator::QueryParameter => true,
-                        UriTemplateOperator::QueryContinuation => true,
-                    }
-                ).unwrap_or(false);
+    pub fn to_string_with_values<V: UriTemplateVars + ?Sized>(&self, values: &V) -> String {
+        let options = expanding::UriTemplateExpansionOptions::new();
+        let mut expanded = String::new();
+        match expanding::ExpansionState::new(&options).expand_components(slice::from_ref(self), values, &options, &mut expanded) {
+            Ok(()) => expanded,
+            Err(_) => unreachable!("expansion with the default options cannot fail"),
+        }
+    }
 
-                let include_equals_when_empty: bool = operator.map(|o|
-                    match o {
-                        UriTemplateOperator::ReservedCharacter => false,
-                        UriTemplateOperator::Fragment => false,
-                        UriTemplateOperator::PathExtension => false,
-                        UriTemplateOperator::PathComponent => false,
-                        UriTemplateOperator::PathParameter => false,
-                        UriTemplateOperator::QueryParameter => true,
-                        UriTemplateOperator::QueryContinuation => true,
-                    }
-                ).unwrap_or(false);
-
-                let values: Vec<String> = variables.iter().filter_map(|v| {
-                    let values: Vec<String> = match v {
-                        &UriTemplateVariable{ ref name, prefix, explode: _ } => {
-                            let mut strings: Vec<String> = values.strings_for_name(name);
-                            if let Some(prefix) = prefix {
-                                strings = UriTemplateComponent::strings_apply_prefix(strings, prefix);
-                            }
-                            UriTemplateComponent::strings_apply_escaping(strings, escaping)
+    pub(crate) fn expand<V: UriTemplateVars + ?Sized, S: Sink>(&self, values: &V, options: &expanding::UriTemplateExpansionOptions, out: &mut expanding::ExpansionWriter<S>) -> Result<(), expanding::UriTemplateExpansionError> {
+        let in_query = out.state.in_query();
+        let in_fragment = out.state.in_fragment();
+        match *self {
+            UriTemplateComponent::Literal(ref value) => {
+                let escaper = escaping::Escaper {
+                    set: escaping::EscapeSet::reserved(),
+                    lowercase_hex: options.lowercase_hex,
+                    iri: options.iri,
+                    iprivate: false,
+                };
+                let start = out.len;
+                escaper.escape_literal(value, in_query, in_fragment, out);
+                out.check_length()?;
+                out.span(start, None, false);
+            },
+            UriTemplateComponent::Variable(operator, ref variables) => {
+                let rules = OperatorRules::new(operator);
+                let prefix = match operator {
+                    Some(UriTemplateOperator::QueryParameter) if options.query_aware && in_query => "&",
+                    Some(UriTemplateOperator::QueryContinuation) if options.query_aware && !in_query && !in_fragment => "?",
+                    _ => rules.prefix,
+                };
+
+                let reserved = operator == Some(UriTemplateOperator::ReservedCharacter);
+                let check_path = options.dot_segments == expanding::UriTemplateDotSegments::Reject && out.state.in_path(operator) &&
+                    (operator.is_none() || reserved || operator == Some(UriTemplateOperator::PathComponent));
+                let path_check = if check_path { Some(reserved) } else { None };
+
+                let escaper = escaping::Escaper {
+                    set: options.escape_set_for(operator),
+                    lowercase_hex: options.lowercase_hex,
+                    iri: options.iri,
+                    iprivate: match operator {
+                        Some(UriTemplateOperator::QueryParameter) | Some(UriTemplateOperator::QueryContinuation) => !in_fragment,
+                        Some(UriTemplateOperator::Fragment) => false,
+                        _ => in_query,
+                    },
+                };
+
+                #[cfg(feature = "idna")]
+                let in_host = options.idna && out.state.in_host() && (operator.is_none() || operator == Some(UriTemplateOperator::ReservedCharacter));
+
+                let mut first = true;
+                for (i, v) in variables.iter().enumerate() {
+                    let start = out.len;
+                    let value = match (values.uri_template_value(&v.name).filter(|value| !value.is_undefined()), &v.default) {
+                        (Some(value), _) => value,
+                        (None, Some(default)) => Cow::Owned(UriTemplateValue::String(default.clone())),
+                        (None, None) => {
+                            out.span(start, Some(i), true);
+                            continue;
+                        },
+                    };
+                    #[cfg(feature = "idna")]
+                    let value = if in_host { Cow::Owned(expanding::host_to_ascii(&v.name, &value)?) } else { value };
+                    let escaper = if escaper.set.allows_delimiters() && values.is_untrusted(&v.name) {
+                        match options.untrusted {
+                            expanding::UriTemplateUntrustedPolicy::Allow => escaper,
+                            expanding::UriTemplateUntrustedPolicy::Reject => {
+                                return Err(expanding::UriTemplateExpansionError::UntrustedValue(v.name.clone()));
+                            },
+                            expanding::UriTemplateUntrustedPolicy::Restrict(set) => {
+                                escaping::Escaper { set, ..escaper }
+                            },
                         }
+                    } else {
+                        escaper
+                    };
+                    let length = match *value {
+                        UriTemplateValue::String(_) => 1,
+                        UriTemplateValue::List(ref strings) => strings.len(),
+                        UriTemplateValue::AssociativeArray(ref pairs) => pairs.len(),
                     };
-                    if values.len() == 0 {
-                        return None;
+                    if let Some(max_list_length) = options.max_list_length {
+                        if length > max_list_length {
+                            return Err(expanding::UriTemplateExpansionError::ListTooLong(v.name.clone()));
+                        }
                     }
-                    Some(match v {
-                        &UriTemplateVariable{ ref name, prefix: _, explode: false } => {
-                            let mut value = values.join(",");
-                            if include_name {
-                                if value.len() != 0 || include_equals_when_empty {
-                                    value = format!("{}={}", name, value);
-                                } else {
-                                    value = name.clone();
-                                }
-                            }
-                            value
+                    out.push_str(if first { prefix } else { rules.separator });
+                    first = false;
+                    match *value {
+                        UriTemplateValue::String(ref string) => {
+                            UriTemplateComponent::expand_items(v, slice::from_ref(string), escaper, &rules, path_check, out)?;
                         },
-                        &UriTemplateVariable{ ref name, prefix: _, explode: true } => {
-                            if include_name {
-                                let strings: Vec<String> = values.into_iter().map(|s| { format!("{}={}", name, s) }).collect();
-                                strings.join(separator)
-                            } else {
-                                values.join(separator)
-                            }
+                        UriTemplateValue::List(ref strings) => {
+                            UriTemplateComponent::expand_items(v, strings, escaper, &rules, path_check, out)?;
                         },
-                    })
-                }).collect();
-
-                if values.len() == 0 {
-                    "".to_string()
-                } else {
-                    format!("{}{}", prefix, values.join(separator))
+                        UriTemplateValue::AssociativeArray(ref pairs) => {
+                            UriTemplateComponent::expand_pairs(v, pairs, escaper, &rules, out)?;
+                        },
+                    }
+                    out.span(start, Some(i), false);
                 }
             },
         }
+        Ok(())
+    }
+
+    // A bound on the length of the expansion with the default options that
+    // takes time proportional to the number of values rather than their
+    // length: every byte of a value or literal may be pct-encoded, and the
+    // prefix or a separator and the name may precede every item.
+    pub(crate) fn expanded_len_upper_bound<V: UriTemplateVars + ?Sized>(&self, values: &V) -> usize {
+        match *self {
+            UriTemplateComponent::Literal(ref value) => 3 * value.len(),
+            UriTemplateComponent::Variable(operator, ref variables) => {
+                let rules = OperatorRules::new(operator);
+                variables.iter().map(|v| {
+                    let value = match (values.uri_template_value(&v.name).filter(|value| !value.is_undefined()), &v.default) {
+                        (Some(value), _) => value,
+                        (None, Some(default)) => Cow::Owned(UriTemplateValue::String(default.clone())),
+                        (None, None) => return 0,
+                    };
+                    let name = if rules.include_name { v.name.len() + 1 } else { 0 };
+                    let item = |item: &String| 1 + name + 3 * v.prefix.map_or(item.len(), |prefix| cmp::min(item.len(), 4 * prefix as usize));
+                    match *value {
+                        UriTemplateValue::String(ref string) => item(string),
+                        UriTemplateValue::List(ref strings) => strings.iter().map(item).sum(),
+                        UriTemplateValue::AssociativeArray(ref pairs) => {
+                            1 + name + pairs.iter().map(|(key, value)| 2 + 3 * (key.len() + value.len())).sum::<usize>()
+                        },
+                    }
+                }).sum()
+            },
+        }
     }
 }
 
 
-#[derive(Clone,PartialEq,Eq)]
+// How an operator expands its variables (RFC 6570 appendix A), apart from
+// the characters it leaves unescaped.
+struct OperatorRules {
+    prefix: &'static str,
+    separator: &'static str,
+    include_name: bool,
+    include_equals_when_empty: bool,
+}
+
+impl OperatorRules {
+    fn new(operator: Option<UriTemplateOperator>) -> OperatorRules {
+        let prefix: &'static str = operator.map(|o|
+            match o {
+                UriTemplateOperator::ReservedCharacter => "",
+                UriTemplateOperator::Fragment => "#",
+                UriTemplateOperator::PathExtension => ".",
+                UriTemplateOperator::PathComponent => "/",
+                UriTemplateOperator::PathParameter => ";",
+                UriTemplateOperator::QueryParameter => "?",
+                UriTemplateOperator::QueryContinuation => "&",
+            }
+        ).unwrap_or("");
+
+        let separator: &'static str = operator.map(|o|
+            match o {
+                UriTemplateOperator::ReservedCharacter => ",",
+                UriTemplateOperator::Fragment => ",",
+                UriTemplateOperator::PathExtension => ".",
+                UriTemplateOperator::PathComponent => "/",
+                UriTemplateOperator::PathParameter => ";",
+                UriTemplateOperator::QueryParameter => "&",
+                UriTemplateOperator::QueryContinuation => "&",
+            }
+        ).unwrap_or(",");
+
+        let include_name: bool = operator.map(|o|
+            match o {
+                UriTemplateOperator::ReservedCharacter => false,
+                UriTemplateOperator::Fragment => false,
+                UriTemplateOperator::PathExtension => false,
+                UriTemplateOperator::PathComponent => false,
+                UriTemplateOperator::PathParameter => true,
+                UriTemplateOperator::QueryParameter => true,
+                UriTemplateOperator::QueryContinuation => true,
+            }
+        ).unwrap_or(false);
+
+        let include_equals_when_empty: bool = operator.map(|o|
+            match o {
+                UriTemplateOperator::ReservedCharacter => false,
+                UriTemplateOperator::Fragment => false,
+                UriTemplateOperator::PathExtension => false,
+                UriTemplateOperator::PathComponent => false,
+                UriTemplateOperator::PathParameter => false,
+                UriTemplateOperator::QueryParameter => true,
+                UriTemplateOperator::QueryContinuation => true,
+            }
+        ).unwrap_or(false);
+
+        OperatorRules {
+            prefix,
+            separator,
+            include_name,
+            include_equals_when_empty,
+        }
+    }
+}
+
+#[derive(Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
 pub struct UriTemplate {
     components: Vec<UriTemplateComponent>,
 }
@@ -305,12 +509,25 @@ impl UriTemplate {
         building::UriTemplateBuilder::new()
     }
 
+    pub fn parse(template: &str) -> Result<UriTemplate, parsing::UriTemplateParseError> {
+        parsing::parse(template)
+    }
+
+    /// Parses the template, enforcing the limits in `options`.
+    pub fn parse_with_options(template: &str, options: &parsing::UriTemplateParseOptions) -> Result<UriTemplate, parsing::UriTemplateParseError> {
+        parsing::parse_with_options(template, options)
+    }
+
     pub fn from_components(components: Vec<UriTemplateComponent>) -> UriTemplate {
         UriTemplate {
-            components: components,
+            components,
         }
     }
 
+    pub fn components(&self) -> &[UriTemplateComponent] {
+        &self.components
+    }
+
     pub fn to_template_string(&self) -> String {
         let components: Vec<String> = self.components.iter().map(|c|
             c.to_template_string()
@@ -325,24 +542,121 @@ impl UriTemplate {
         components.concat()
     }
 
-    pub fn to_string_with_values(&self, values: &UriTemplateValues) -> String {
-        let components: Vec<String> = self.components.iter().map(|c|
-            c.to_string_with_values(values)
-        ).collect();
-        components.concat()
+    pub fn to_string_with_values<V: UriTemplateVars + ?Sized>(&self, values: &V) -> String {
+        match self.to_string_with_options(values, &expanding::UriTemplateExpansionOptions::new()) {
+            Ok(expanded) => expanded,
+            Err(_) => unreachable!("expansion with the default options cannot fail"),
+        }
+    }
+
+    /// Pairs the template with values, for conversions that need both.
+    pub fn with_values<'a, V: UriTemplateVars + ?Sized>(&'a self, values: &'a V) -> UriTemplateExpansion<'a, V> {
+        UriTemplateExpansion {
+            template: self,
+            values,
+        }
     }
 }
 
-impl std::fmt::Debug for UriTemplate {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+/// Displays as the template string, which parses back to an equal template
+/// unless the template was built with `UriTemplateModifier::ExplodePrefix`,
+/// which has no RFC 6570 syntax.
+impl fmt::Display for UriTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.pad(self.to_template_string().as_ref())
     }
 }
 
+impl FromStr for UriTemplate {
+    type Err = parsing::UriTemplateParseError;
+
+    fn from_str(s: &str) -> Result<UriTemplate, parsing::UriTemplateParseError> {
+        UriTemplate::parse(s)
+    }
+}
+
 
+/// A template together with the values to expand it with.
+pub struct UriTemplateExpansion<'a, V: UriTemplateVars + ?Sized + 'a> {
+    template: &'a UriTemplate,
+    values: &'a V,
+}
+
+impl<'a, V: UriTemplateVars + ?Sized + 'a> UriTemplateExpansion<'a, V> {
+    pub fn template(&self) -> &'a UriTemplate {
+        self.template
+    }
+
+    pub fn values(&self) -> &'a V {
+        self.values
+    }
+}
+
+impl<'a, V: UriTemplateVars + ?Sized + 'a> Clone for UriTemplateExpansion<'a, V> {
+    fn clone(&self) -> UriTemplateExpansion<'a, V> {
+        *self
+    }
+}
+
+impl<'a, V: UriTemplateVars + ?Sized + 'a> Copy for UriTemplateExpansion<'a, V> {}
+
+impl<'a, V: UriTemplateVars + fmt::Debug + ?Sized + 'a> fmt::Debug for UriTemplateExpansion<'a, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UriTemplateExpansion")
+            .field("template", &self.template)
+            .field("values", &self.values)
+            .finish()
+    }
+}
+
+impl<'a, V: UriTemplateVars + ?Sized + 'a> fmt::Display for UriTemplateExpansion<'a, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.template.to_string_with_values(self.values))
+    }
+}
+
+
+#[derive(Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
 pub enum UriTemplateValue {
     String(String),
     List(Vec<String>),
+    AssociativeArray(Vec<(String, String)>),
+}
+
+impl UriTemplateValue {
+    // Empty lists and associative arrays are undefined (RFC 6570 section
+    // 2.3).
+    pub(crate) fn is_undefined(&self) -> bool {
+        match *self {
+            UriTemplateValue::String(_) => false,
+            UriTemplateValue::List(ref strings) => strings.is_empty(),
+            UriTemplateValue::AssociativeArray(ref pairs) => pairs.is_empty(),
+        }
+    }
+
+    /// Parses a single value with `FromStr`, describing the error if it
+    /// cannot be parsed or the value is a list or associative array.
+    pub fn parse<T: FromStr>(&self) -> Result<T, String> where T::Err: fmt::Display {
+        match *self {
+            UriTemplateValue::String(ref string) => string.parse().map_err(|e: T::Err| e.to_string()),
+            _ => Err("expected a single value".to_string()),
+        }
+    }
+}
+
+/// Displays unescaped in the form of a simple string expansion: list items
+/// and associative array keys and values are joined with `,`.
+impl fmt::Display for UriTemplateValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UriTemplateValue::String(ref string) => f.pad(string),
+            UriTemplateValue::List(ref strings) => f.pad(&strings.join(",")),
+            UriTemplateValue::AssociativeArray(ref pairs) => {
+                let strings: Vec<String> = pairs.iter().map(|(k, v)| format!("{},{}", k, v)).collect();
+                f.pad(&strings.join(","))
+            },
+        }
+    }
 }
 
 impl<'a> From<&'a str> for UriTemplateValue {
@@ -370,15 +684,126 @@ impl From<Vec<String>> for UriTemplateValue {
     }
 }
 
+impl<'a, 'b> From<&'a [(&'b str, &'b str)]> for UriTemplateValue {
+    fn from(l: &'a [(&'b str, &'b str)]) -> UriTemplateValue {
+        let v: Vec<(String, String)> = l.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect();
+        UriTemplateValue::AssociativeArray(v)
+    }
+}
+
+
+/// Conversion of a typed value into a `UriTemplateValue`, where `None` leaves
+/// the variable undefined.
+pub trait ToUriTemplateValue {
+    fn to_uri_template_value(&self) -> Option<UriTemplateValue>;
+}
+
+impl ToUriTemplateValue for str {
+    fn to_uri_template_value(&self) -> Option<UriTemplateValue> {
+        Some(UriTemplateValue::String(self.to_owned()))
+    }
+}
+
+impl ToUriTemplateValue for String {
+    fn to_uri_template_value(&self) -> Option<UriTemplateValue> {
+        Some(UriTemplateValue::String(self.clone()))
+    }
+}
+
+macro_rules! to_uri_template_value_via_display {
+    ($($t:ty),*) => {
+        $(
+            impl ToUriTemplateValue for $t {
+                fn to_uri_template_value(&self) -> Option<UriTemplateValue> {
+                    Some(UriTemplateValue::String(self.to_string()))
+                }
+            }
+        )*
+    }
+}
+
+to_uri_template_value_via_display!(bool, char, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+
+impl<T: ToUriTemplateValue + ?Sized> ToUriTemplateValue for &T {
+    fn to_uri_template_value(&self) -> Option<UriTemplateValue> {
+        (**self).to_uri_template_value()
+    }
+}
+
+impl<T: ToUriTemplateValue> ToUriTemplateValue for Option<T> {
+    fn to_uri_template_value(&self) -> Option<UriTemplateValue> {
+        self.as_ref().and_then(|v| v.to_uri_template_value())
+    }
+}
+
+impl<T: fmt::Display> ToUriTemplateValue for [T] {
+    fn to_uri_template_value(&self) -> Option<UriTemplateValue> {
+        Some(UriTemplateValue::List(self.iter().map(|v| v.to_string()).collect()))
+    }
+}
+
+impl<T: fmt::Display> ToUriTemplateValue for Vec<T> {
+    fn to_uri_template_value(&self) -> Option<UriTemplateValue> {
+        self[..].to_uri_template_value()
+    }
+}
+
+impl<K: fmt::Display, V: fmt::Display> ToUriTemplateValue for BTreeMap<K, V> {
+    fn to_uri_template_value(&self) -> Option<UriTemplateValue> {
+        Some(UriTemplateValue::AssociativeArray(self.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()))
+    }
+}
+
+/// Pairs are ordered by key so that expansion is deterministic.
+impl<K: fmt::Display + Eq + Hash, V: fmt::Display, S: BuildHasher> ToUriTemplateValue for HashMap<K, V, S> {
+    fn to_uri_template_value(&self) -> Option<UriTemplateValue> {
+        let mut pairs: Vec<(String, String)> = self.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        pairs.sort();
+        Some(UriTemplateValue::AssociativeArray(pairs))
+    }
+}
+
+
+/// Source of variable values for expansion.
+pub trait UriTemplateVars {
+    fn uri_template_value(&self, name: &str) -> Option<Cow<'_, UriTemplateValue>>;
+
+    /// Whether the value of `name` comes from an untrusted source, and so is
+    /// subject to `UriTemplateExpansionOptions::untrusted` in `+` and `#`
+    /// expressions.
+    fn is_untrusted(&self, _name: &str) -> bool {
+        false
+    }
+}
+
+impl<T: UriTemplateVars + ?Sized> UriTemplateVars for &T {
+    fn uri_template_value(&self, name: &str) -> Option<Cow<'_, UriTemplateValue>> {
+        (**self).uri_template_value(name)
+    }
+
+    fn is_untrusted(&self, name: &str) -> bool {
+        (**self).is_untrusted(name)
+    }
+}
+
 
+#[derive(Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
 pub struct UriTemplateValues {
-    values: HashMap<String, UriTemplateValue>,
+    values: BTreeMap<String, UriTemplateValue>,
+    untrusted: BTreeSet<String>,
+}
+
+impl Default for UriTemplateValues {
+    fn default() -> UriTemplateValues {
+        UriTemplateValues::new()
+    }
 }
 
 impl UriTemplateValues {
     pub fn new() -> UriTemplateValues {
         UriTemplateValues {
-            values: HashMap::new(),
+            values: BTreeMap::new(),
+            untrusted: BTreeSet::new(),
         }
     }
 
@@ -386,24 +811,59 @@ impl UriTemplateValues {
         where N: Into<String>,
               V: Into<UriTemplateValue>
     {
-        self.values.insert(name.into(), value.into());
+        let name = name.into();
+        self.untrusted.remove(&name);
+        self.values.insert(name, value.into());
         self
     }
 
-    fn strings_for_name(&self, name: &String) -> Vec<String> {
-        self.values.get(name).map(|value| {
-            match value {
-                &UriTemplateValue::String(ref string) => vec!(string.clone()),
-                &UriTemplateValue::List(ref strings) => strings.clone(),
-            }
-        }).unwrap_or(Vec::new())
+    /// Sets `name` like `set`, marking the value as untrusted (see
+    /// `UriTemplateVars::is_untrusted`).
+    pub fn set_untrusted<N, V>(&mut self, name: N, value: V) -> &mut UriTemplateValues
+        where N: Into<String>,
+              V: Into<UriTemplateValue>
+    {
+        let name = name.into();
+        self.untrusted.insert(name.clone());
+        self.values.insert(name, value.into());
+        self
+    }
+
+    /// Sets `name` from a typed value, leaving it undefined if the value
+    /// converts to `None`.
+    pub fn set_typed<N, V>(&mut self, name: N, value: &V) -> &mut UriTemplateValues
+        where N: Into<String>,
+              V: ToUriTemplateValue + ?Sized
+    {
+        if let Some(value) = value.to_uri_template_value() {
+            let name = name.into();
+            self.untrusted.remove(&name);
+            self.values.insert(name, value);
+        }
+        self
+    }
+
+    // The defined names and their values, ordered by name.
+    #[cfg(feature = "http")]
+    pub(crate) fn iter(&self) -> btree_map::Iter<'_, String, UriTemplateValue> {
+        self.values.iter()
+    }
+}
+
+impl UriTemplateVars for UriTemplateValues {
+    fn uri_template_value(&self, name: &str) -> Option<Cow<'_, UriTemplateValue>> {
+        self.values.get(name).map(Cow::Borrowed)
+    }
+
+    fn is_untrusted(&self, name: &str) -> bool {
+        self.untrusted.contains(name)
     }
 }
 
 
 #[cfg(test)]
 mod test_values {
-    use super::{UriTemplateValues};
+    use super::{UriTemplateValue, UriTemplateValues, UriTemplateVars};
 
     #[test]
     fn test_values_1() {
@@ -424,11 +884,37 @@ mod test_values {
         let mut v = UriTemplateValues::new();
         v.set("foo", "baz");
     }
+
+    #[test]
+    fn test_values_untrusted() {
+        let mut v = UriTemplateValues::new();
+        v.set_untrusted("q", "a");
+        v.set("id", "1");
+        assert!(v.is_untrusted("q"));
+        assert!(!v.is_untrusted("id"));
+        assert!(!v.is_untrusted("missing"));
+        v.set("q", "b");
+        assert!(!v.is_untrusted("q"));
+        v.set_untrusted("q", "c");
+        v.set_typed("q", &1);
+        assert!(!v.is_untrusted("q"));
+    }
+
+    #[test]
+    fn test_values_typed() {
+        let mut v = UriTemplateValues::new();
+        v.set_typed("id", &42u32);
+        v.set_typed("tags", &vec!(1, 2));
+        v.set_typed("missing", &None::<String>);
+        assert!(v.values["id"] == UriTemplateValue::String("42".to_string()));
+        assert!(v.values["tags"] == UriTemplateValue::List(vec!("1".to_string(), "2".to_string())));
+        assert!(!v.values.contains_key("missing"));
+    }
 }
 
 #[cfg(test)]
 mod test_expanding {
-    use super::super::{UriTemplateBuilder, UriTemplateValues};
+    use super::super::{UriTemplate, UriTemplateBuilder, UriTemplateOperator, UriTemplateParseOptions, UriTemplateValues};
 
     #[test]
     fn test_level_1_1() {
@@ -483,4 +969,93 @@ mod test_expanding {
         let s = t.to_string_with_values(&v);
         assert_eq!(s, "http://example.com/bar,baz");
     }
+
+    #[test]
+    fn test_defaults() {
+        let mut v = UriTemplateValues::new();
+        v.set("q", "cat");
+        v.set("none", [""; 0].as_ref());
+        let options = UriTemplateParseOptions::new().strict(false);
+        let expand = |template: &str| UriTemplate::parse_with_options(template, &options).unwrap().to_string_with_values(&v);
+        assert_eq!(expand("/search{?q,format=json}"), "/search?q=cat&format=json");
+        assert_eq!(expand("/search{?q=dog}"), "/search?q=cat");
+        assert_eq!(expand("{x=a%20b}"), "a%2520b");
+        assert_eq!(expand("{+x=a%20b}"), "a%20b");
+        assert_eq!(expand("{/none=a/b}"), "/a%2Fb");
+        assert_eq!(expand("{+none=a/b}"), "a/b");
+        assert_eq!(expand("{?x=,y:2=abc}"), "?x=&y=ab");
+        assert_eq!(expand("{?x=json}").len(), UriTemplate::parse_with_options("{?x=json}", &options).unwrap().expanded_len(&v));
+    }
+
+    #[test]
+    fn test_pct_encoded_passthrough() {
+        let t = UriTemplateBuilder::new()
+        .literal("http://example.com/%7efoo/")
+        .component(Some(UriTemplateOperator::ReservedCharacter), |c| c.variable("foo", None))
+        .component(None, |c| c.variable("foo", None))
+        .into_uri_template();
+
+        let mut v = UriTemplateValues::new();
+        v.set("foo", "a%20b%2");
+
+        let s = t.to_string_with_values(&v);
+        assert_eq!(s, "http://example.com/%7efoo/a%20b%252a%2520b%252");
+    }
+}
+
+
+#[cfg(test)]
+mod test_traits {
+    use std::collections::{BTreeSet, HashSet};
+    use super::{UriTemplate, UriTemplateOperator, UriTemplateValue, UriTemplateValues};
+    use super::super::building::UriTemplateModifier;
+
+    #[test]
+    fn test_display_round_trip() {
+        for template in ["http://example.com/{a}", "{+path:6}/here", "{#x,hello,y}", "X{.list*}", "{/var:1,var}", "{;keys*}", "{?x,y}{&z}"].iter() {
+            let t = UriTemplate::parse(template).unwrap();
+            assert_eq!(t.to_string(), *template);
+            assert_eq!(UriTemplate::parse(&t.to_string()).unwrap(), t);
+            for component in t.components().iter() {
+                assert_eq!(UriTemplate::parse(&component.to_string()).unwrap().components()[0], *component);
+            }
+        }
+        assert_eq!(format!("{:>6}", UriTemplate::parse("{x}").unwrap()), "   {x}");
+    }
+
+    #[test]
+    fn test_display_parts() {
+        assert_eq!(UriTemplateOperator::QueryParameter.to_string(), "?");
+        assert_eq!(UriTemplateModifier::Prefix(3).to_string(), ":3");
+        assert_eq!(UriTemplateModifier::ExplodePrefix(3).to_string(), "*:3");
+        assert_eq!(UriTemplateValue::from("a b").to_string(), "a b");
+        assert_eq!(UriTemplateValue::from(["a", "b"].as_ref()).to_string(), "a,b");
+        assert_eq!(UriTemplateValue::from([("k", "v"), ("x", "y")].as_ref()).to_string(), "k,v,x,y");
+    }
+
+    #[test]
+    fn test_debug() {
+        let t = UriTemplate::parse("{?x}").unwrap();
+        assert_eq!(format!("{:?}", t.components()[0]),
+            "Variable(Some(QueryParameter), [UriTemplateVariable { name: \"x\", explode: false, prefix: None, constraint: None, default: None }])");
+        let mut v = UriTemplateValues::new();
+        v.set("x", "1");
+        assert_eq!(format!("{:?}", t.with_values(&v)),
+            format!("UriTemplateExpansion {{ template: {:?}, values: {:?} }}", t, v));
+    }
+
+    #[test]
+    fn test_hash_and_ord() {
+        let a = UriTemplate::parse("/a{x}").unwrap();
+        let b = UriTemplate::parse("/b{x}").unwrap();
+        let hashed: HashSet<UriTemplate> = vec!(a.clone(), b.clone(), a.clone()).into_iter().collect();
+        assert_eq!(hashed.len(), 2);
+        let ordered: BTreeSet<UriTemplate> = vec!(b.clone(), a.clone()).into_iter().collect();
+        assert_eq!(ordered.into_iter().collect::<Vec<_>>(), vec!(a, b));
+
+        let mut v = UriTemplateValues::new();
+        v.set("x", "1");
+        let values: HashSet<UriTemplateValues> = vec!(v.clone(), v).into_iter().collect();
+        assert_eq!(values.len(), 1);
+    }
 }
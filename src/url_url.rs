@@ -0,0 +1,111 @@
+use std::error::Error;
+use std::fmt;
+
+use url::{ParseError, Url};
+
+use super::types::{UriTemplate, UriTemplateVars};
+
+
+/// An expansion that could not be parsed or resolved as a `url::Url`.
+#[derive(Debug)]
+pub struct UriTemplateUrlError {
+    template: String,
+    expanded: String,
+    source: ParseError,
+}
+
+impl UriTemplateUrlError {
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    pub fn expanded(&self) -> &str {
+        &self.expanded
+    }
+}
+
+impl fmt::Display for UriTemplateUrlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "template {:?} expanded to invalid URL {:?}: {}", self.template, self.expanded, self.source)
+    }
+}
+
+impl Error for UriTemplateUrlError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+
+impl UriTemplate {
+    /// Expands the template and resolves the result as a URI reference
+    /// against `base` (RFC 3986 section 5), removing dot segments.
+    ///
+    /// Without a base the expansion must be an absolute URL.
+    pub fn expand_url<V: UriTemplateVars + ?Sized>(&self, values: &V, base: Option<&Url>) -> Result<Url, UriTemplateUrlError> {
+        let expanded = self.to_string_with_values(values);
+        Url::options().base_url(base).parse(&expanded).map_err(|source| UriTemplateUrlError {
+            template: self.to_template_string(),
+            expanded,
+            source,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use url::{ParseError, Url};
+
+    use super::super::{UriTemplate, UriTemplateValues};
+
+    fn values() -> UriTemplateValues {
+        let mut v = UriTemplateValues::new();
+        v.set("page", "2");
+        v.set("section", "items");
+        v.set("segments", ["a", "..", "b"].as_ref());
+        v
+    }
+
+    fn base() -> Url {
+        Url::parse("https://api.example.com/v1/customers/7?expand=1#top").unwrap()
+    }
+
+    fn expand(template: &str, base: Option<&Url>) -> String {
+        UriTemplate::parse(template).unwrap().expand_url(&values(), base).unwrap().into()
+    }
+
+    #[test]
+    fn test_absolute() {
+        assert_eq!(expand("https://example.com/orders{?page}", None), "https://example.com/orders?page=2");
+        assert_eq!(expand("https://example.com/orders{?page}", Some(&base())), "https://example.com/orders?page=2");
+    }
+
+    #[test]
+    fn test_absolute_path() {
+        assert_eq!(expand("/orders{?page}", Some(&base())), "https://api.example.com/orders?page=2");
+    }
+
+    #[test]
+    fn test_dot_segments() {
+        assert_eq!(expand("../orders{?page}", Some(&base())), "https://api.example.com/v1/orders?page=2");
+        assert_eq!(expand("./8", Some(&base())), "https://api.example.com/v1/customers/8");
+        assert_eq!(expand("/v1{/segments*}", Some(&base())), "https://api.example.com/v1/b");
+    }
+
+    #[test]
+    fn test_query_and_fragment() {
+        assert_eq!(expand("{?page}", Some(&base())), "https://api.example.com/v1/customers/7?page=2");
+        assert_eq!(expand("{#section}", Some(&base())), "https://api.example.com/v1/customers/7?expand=1#items");
+        assert_eq!(expand("{?undefined}", Some(&base())), "https://api.example.com/v1/customers/7?expand=1");
+    }
+
+    #[test]
+    fn test_relative_without_base() {
+        let t = UriTemplate::parse("/orders{?page}").unwrap();
+        let e = t.expand_url(&values(), None).unwrap_err();
+        assert_eq!(e.template(), "/orders{?page}");
+        assert_eq!(e.expanded(), "/orders?page=2");
+        assert_eq!(e.to_string(), format!("template \"/orders{{?page}}\" expanded to invalid URL \"/orders?page=2\": {}", ParseError::RelativeUrlWithoutBase));
+    }
+}
@@ -0,0 +1,114 @@
+extern crate regex;
+extern crate uritemplates;
+
+use regex::Regex;
+
+use uritemplates::{UriTemplate, UriTemplateRegexOptions, UriTemplateValues};
+
+
+fn regex(template: &str, options: UriTemplateRegexOptions) -> Regex {
+    let t = UriTemplate::parse(template).unwrap();
+    Regex::new(&t.to_regex_string(&options)).unwrap()
+}
+
+fn capture(re: &Regex, uri: &str, group: &str) -> Option<String> {
+    re.captures(uri).and_then(|c| c.name(group).map(|m| m.as_str().to_string()))
+}
+
+
+#[test]
+fn test_anchored_literal() {
+    let t = UriTemplate::parse("/a.b(c)").unwrap();
+    assert_eq!(t.to_regex_string(&UriTemplateRegexOptions::new()), r"^/a\.b\(c\)$");
+
+    let re = regex("/a.b(c)", UriTemplateRegexOptions::new());
+    assert!(re.is_match("/a.b(c)"));
+    assert!(!re.is_match("/aXb(c)"));
+    assert!(!re.is_match("/a.b(c)/more"));
+}
+
+#[test]
+fn test_simple() {
+    let re = regex("/users/{id}/posts", UriTemplateRegexOptions::new());
+    assert_eq!(capture(&re, "/users/42/posts", "id").unwrap(), "42");
+    assert_eq!(capture(&re, "/users/hello%20world/posts", "id").unwrap(), "hello%20world");
+    assert!(!re.is_match("/users/a/b/posts"));
+}
+
+#[test]
+fn test_operators() {
+    let re = regex("/files{/path}{.ext}{;v}{?q,page}{#frag}", UriTemplateRegexOptions::new());
+    let c = re.captures("/files/readme.md;v=2?q=rust&page=3#intro").unwrap();
+    assert_eq!(&c["path"], "readme");
+    assert_eq!(&c["ext"], "md");
+    assert_eq!(&c["v"], "2");
+    assert_eq!(&c["q"], "rust");
+    assert_eq!(&c["page"], "3");
+    assert_eq!(&c["frag"], "intro");
+
+    let c = re.captures("/files?page=3").unwrap();
+    assert!(c.name("path").is_none());
+    assert!(c.name("q").is_none());
+    assert_eq!(&c["page"], "3");
+}
+
+#[test]
+fn test_reserved() {
+    let re = regex("{+base}/index{?x}", UriTemplateRegexOptions::new());
+    assert_eq!(capture(&re, "http://example.com/home/index", "base").unwrap(), "http://example.com/home");
+}
+
+#[test]
+fn test_matches_expansions() {
+    let mut v = UriTemplateValues::new();
+    v.set("list", ["red", "green", "blue"].as_ref());
+    v.set("keys", [("comma", ","), ("dot", "."), ("semi", ";")].as_ref());
+    v.set("var", "value");
+    v.set("empty", "");
+
+    let templates = [
+        "{var:3}", "{list}", "{list*}", "{keys}", "{keys*}", "{+list*}", "X{.list*}", "{/list*,var}",
+        "{;list*}", "{;empty}", "{?list*}", "{?keys*}", "{&var,empty}", "{#keys*}",
+    ];
+    for template in templates.iter() {
+        let expanded = UriTemplate::parse(template).unwrap().to_string_with_values(&v);
+        assert!(regex(template, UriTemplateRegexOptions::new()).is_match(&expanded), "{} {}", template, expanded);
+    }
+}
+
+#[test]
+fn test_strict() {
+    let loose = regex("/{hash:3}{?tags*}", UriTemplateRegexOptions::new());
+    let strict = regex("/{hash:3}{?tags*}", UriTemplateRegexOptions::new().strict(true));
+
+    assert!(loose.is_match("/abcdef?tags=a&tags=b"));
+    assert!(strict.is_match("/abc?tags=a&tags=b"));
+    assert!(!strict.is_match("/abcdef?tags=a&tags=b"));
+    assert!(loose.is_match("/abc?tags=a&other=b"));
+    assert!(!strict.is_match("/abc?tags=a&other=b"));
+    assert_eq!(capture(&strict, "/abc?tags=a&tags=b", "tags").unwrap(), "tags=a&tags=b");
+}
+
+#[test]
+fn test_strict_prefix_pct_encoded() {
+    let mut v = UriTemplateValues::new();
+    v.set("x", "\u{e9}t\u{e9}s");
+    let expanded = UriTemplate::parse("/{x:3}").unwrap().to_string_with_values(&v);
+    assert_eq!(expanded, "/%C3%A9t%C3%A9");
+
+    let strict = regex("/{x:3}", UriTemplateRegexOptions::new().strict(true));
+    assert_eq!(capture(&strict, &expanded, "x").unwrap(), "%C3%A9t%C3%A9");
+    assert!(strict.is_match("/%20%20%20"));
+    assert!(!strict.is_match("/%C3%A9t%C3%A9s"));
+    assert!(!strict.is_match("/%20%20%20%20"));
+}
+
+#[test]
+fn test_group_names() {
+    let t = UriTemplate::parse("/{a.b}/{a_b}/{2x}").unwrap();
+    let re = Regex::new(&t.to_regex_string(&UriTemplateRegexOptions::new())).unwrap();
+    let c = re.captures("/1/2/3").unwrap();
+    assert_eq!(&c["a_b"], "1");
+    assert_eq!(&c["a_b_2"], "2");
+    assert_eq!(&c["_2x"], "3");
+}
@@ -342,7 +342,7 @@ fn test_level_4_values() -> UriTemplateValues {
     v.set("hello", "Hello World!");
     v.set("path", "/foo/bar");
     v.set("list", ["red", "green", "blue"].as_ref());
-    // v.set_assoc("keys", {"semi": ";", "dot": ".", "comma": ","});
+    v.set("keys", [("comma", ","), ("dot", "."), ("semi", ";")].as_ref());
     v.set("empty", "");
     v.set("x", "1024");
     v.set("y", "768");
@@ -403,7 +403,7 @@ fn test_level_4_d() {
 
 #[test]
 fn test_level_4_e() {
-    // let v = test_level_4_values();
+    let v = test_level_4_values();
 
     let t = UriTemplateBuilder::new()
         .component(None, |c|
@@ -411,12 +411,12 @@ fn test_level_4_e() {
         )
         .into_uri_template();
     assert_eq!(t.to_template_string(), "{keys}");
-    // assert_eq!(t.to_string_with_values(&v), "comma,%2C,dot,.,semi,%3B");
+    assert_eq!(t.to_string_with_values(&v), "comma,%2C,dot,.,semi,%3B");
 }
 
 #[test]
 fn test_level_4_f() {
-    // let v = test_level_4_values();
+    let v = test_level_4_values();
 
     let t = UriTemplateBuilder::new()
         .component(None, |c|
@@ -424,7 +424,7 @@ fn test_level_4_f() {
         )
         .into_uri_template();
     assert_eq!(t.to_template_string(), "{keys*}");
-    // assert_eq!(t.to_string_with_values(&v), "comma=%2C,dot=.,semi=%3B");
+    assert_eq!(t.to_string_with_values(&v), "comma=%2C,dot=.,semi=%3B");
 }
 
 #[test]
@@ -469,7 +469,7 @@ fn test_level_4_i() {
 
 #[test]
 fn test_level_4_j() {
-    // let v = test_level_4_values();
+    let v = test_level_4_values();
 
     let t = UriTemplateBuilder::new()
         .component(Some(UriTemplateOperator::ReservedCharacter), |c|
@@ -477,12 +477,12 @@ fn test_level_4_j() {
         )
         .into_uri_template();
     assert_eq!(t.to_template_string(), "{+keys}");
-    // assert_eq!(t.to_string_with_values(&v), "comma,,,dot,.,semi,;");
+    assert_eq!(t.to_string_with_values(&v), "comma,,,dot,.,semi,;");
 }
 
 #[test]
 fn test_level_4_k() {
-    // let v = test_level_4_values();
+    let v = test_level_4_values();
 
     let t = UriTemplateBuilder::new()
         .component(Some(UriTemplateOperator::ReservedCharacter), |c|
@@ -490,7 +490,7 @@ fn test_level_4_k() {
         )
         .into_uri_template();
     assert_eq!(t.to_template_string(), "{+keys*}");
-    // assert_eq!(t.to_string_with_values(&v), "comma=,,dot=.,semi=;");
+    assert_eq!(t.to_string_with_values(&v), "comma=,,dot=.,semi=;");
 }
 
 #[test]
@@ -535,7 +535,7 @@ fn test_level_4_n() {
 
 #[test]
 fn test_level_4_p() {
-    // let v = test_level_4_values();
+    let v = test_level_4_values();
 
     let t = UriTemplateBuilder::new()
         .component(Some(UriTemplateOperator::Fragment), |c|
@@ -543,12 +543,12 @@ fn test_level_4_p() {
         )
         .into_uri_template();
     assert_eq!(t.to_template_string(), "{#keys}");
-    // assert_eq!(t.to_string_with_values(&v), "#comma,,,dot,.,semi,;");
+    assert_eq!(t.to_string_with_values(&v), "#comma,,,dot,.,semi,;");
 }
 
 #[test]
 fn test_level_4_q() {
-    // let v = test_level_4_values();
+    let v = test_level_4_values();
 
     let t = UriTemplateBuilder::new()
         .component(Some(UriTemplateOperator::Fragment), |c|
@@ -556,7 +556,7 @@ fn test_level_4_q() {
         )
         .into_uri_template();
     assert_eq!(t.to_template_string(), "{#keys*}");
-    // assert_eq!(t.to_string_with_values(&v), "#comma=,,dot=.,semi=;");
+    assert_eq!(t.to_string_with_values(&v), "#comma=,,dot=.,semi=;");
 }
 
 #[test]
@@ -603,7 +603,7 @@ fn test_level_4_t() {
 
 #[test]
 fn test_level_4_u() {
-    // let v = test_level_4_values();
+    let v = test_level_4_values();
 
     let t = UriTemplateBuilder::new()
         .literal("X")
@@ -612,7 +612,7 @@ fn test_level_4_u() {
         )
         .into_uri_template();
     assert_eq!(t.to_template_string(), "X{.keys*}");
-    // assert_eq!(t.to_string_with_values(&v), "X.comma,%2C,dot,.,semi,%3B");
+    assert_eq!(t.to_string_with_values(&v), "X.comma=%2C.dot=..semi=%3B");
 }
 
 #[test]
@@ -671,7 +671,7 @@ fn test_level_4_y() {
 
 #[test]
 fn test_level_4_z() {
-    // let v = test_level_4_values();
+    let v = test_level_4_values();
 
     let t = UriTemplateBuilder::new()
         .component(Some(UriTemplateOperator::PathComponent), |c|
@@ -679,12 +679,12 @@ fn test_level_4_z() {
         )
         .into_uri_template();
     assert_eq!(t.to_template_string(), "{/keys}");
-    // assert_eq!(t.to_string_with_values(&v), "/comma,%2C,dot,.,semi,%3B");
+    assert_eq!(t.to_string_with_values(&v), "/comma,%2C,dot,.,semi,%3B");
 }
 
 #[test]
 fn test_level_4_aa() {
-    // let v = test_level_4_values();
+    let v = test_level_4_values();
 
     let t = UriTemplateBuilder::new()
         .component(Some(UriTemplateOperator::PathComponent), |c|
@@ -692,7 +692,7 @@ fn test_level_4_aa() {
         )
         .into_uri_template();
     assert_eq!(t.to_template_string(), "{/keys*}");
-    // assert_eq!(t.to_string_with_values(&v), "/comma=%2C/dot=./semi=%3B");
+    assert_eq!(t.to_string_with_values(&v), "/comma=%2C/dot=./semi=%3B");
 }
 
 #[test]
@@ -736,7 +736,7 @@ fn test_level_4_ad() {
 
 #[test]
 fn test_level_4_ae() {
-    // let v = test_level_4_values();
+    let v = test_level_4_values();
 
     let t = UriTemplateBuilder::new()
         .component(Some(UriTemplateOperator::PathParameter), |c|
@@ -744,12 +744,12 @@ fn test_level_4_ae() {
         )
         .into_uri_template();
     assert_eq!(t.to_template_string(), "{;keys}");
-    // assert_eq!(t.to_string_with_values(&v), ";keys=comma,%2C,dot,.,semi,%3B");
+    assert_eq!(t.to_string_with_values(&v), ";keys=comma,%2C,dot,.,semi,%3B");
 }
 
 #[test]
 fn test_level_4_af() {
-    // let v = test_level_4_values();
+    let v = test_level_4_values();
 
     let t = UriTemplateBuilder::new()
         .component(Some(UriTemplateOperator::PathParameter), |c|
@@ -757,7 +757,7 @@ fn test_level_4_af() {
         )
         .into_uri_template();
     assert_eq!(t.to_template_string(), "{;keys*}");
-    // assert_eq!(t.to_string_with_values(&v), ";comma=%2C;dot=.;semi=%3B");
+    assert_eq!(t.to_string_with_values(&v), ";comma=%2C;dot=.;semi=%3B");
 }
 
 #[test]
@@ -801,7 +801,7 @@ fn test_level_4_ai() {
 
 #[test]
 fn test_level_4_aj() {
-    // let v = test_level_4_values();
+    let v = test_level_4_values();
 
     let t = UriTemplateBuilder::new()
         .component(Some(UriTemplateOperator::QueryParameter), |c|
@@ -809,12 +809,12 @@ fn test_level_4_aj() {
         )
         .into_uri_template();
     assert_eq!(t.to_template_string(), "{?keys}");
-    // assert_eq!(t.to_string_with_values(&v), "?keys=comma,%2C,dot,.,semi,%3B");
+    assert_eq!(t.to_string_with_values(&v), "?keys=comma,%2C,dot,.,semi,%3B");
 }
 
 #[test]
 fn test_level_4_ak() {
-    // let v = test_level_4_values();
+    let v = test_level_4_values();
 
     let t = UriTemplateBuilder::new()
         .component(Some(UriTemplateOperator::QueryParameter), |c|
@@ -822,7 +822,7 @@ fn test_level_4_ak() {
         )
         .into_uri_template();
     assert_eq!(t.to_template_string(), "{?keys*}");
-    // assert_eq!(t.to_string_with_values(&v), "?comma=%2C&dot=.&semi=%3B");
+    assert_eq!(t.to_string_with_values(&v), "?comma=%2C&dot=.&semi=%3B");
 }
 
 #[test]
@@ -866,7 +866,7 @@ fn test_level_4_an() {
 
 #[test]
 fn test_level_4_ao() {
-    // let v = test_level_4_values();
+    let v = test_level_4_values();
 
     let t = UriTemplateBuilder::new()
         .component(Some(UriTemplateOperator::QueryContinuation), |c|
@@ -874,12 +874,12 @@ fn test_level_4_ao() {
         )
         .into_uri_template();
     assert_eq!(t.to_template_string(), "{&keys}");
-    // assert_eq!(t.to_string_with_values(&v), "&keys=comma,%2C,dot,.,semi,%3B");
+    assert_eq!(t.to_string_with_values(&v), "&keys=comma,%2C,dot,.,semi,%3B");
 }
 
 #[test]
 fn test_level_4_ap() {
-    // let v = test_level_4_values();
+    let v = test_level_4_values();
 
     let t = UriTemplateBuilder::new()
         .component(Some(UriTemplateOperator::QueryContinuation), |c|
@@ -887,5 +887,5 @@ fn test_level_4_ap() {
         )
         .into_uri_template();
     assert_eq!(t.to_template_string(), "{&keys*}");
-    // assert_eq!(t.to_string_with_values(&v), "&comma=%2C&dot=.&semi=%3B");
+    assert_eq!(t.to_string_with_values(&v), "&comma=%2C&dot=.&semi=%3B");
 }
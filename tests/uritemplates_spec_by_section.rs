@@ -17,7 +17,7 @@ fn test_values() -> UriTemplateValues {
     v.set("base", "http://example.com/home/");
     v.set("path", "/foo/bar");
     v.set("list", ["red", "green", "blue"].as_ref());
-    // "keys"       : { "semi" : ";", "dot" : ".", "comma" : ","},
+    v.set("keys", [("comma", ","), ("dot", "."), ("semi", ";")].as_ref());
     v.set("v", "6");
     v.set("x", "1024");
     v.set("y", "768");
@@ -314,7 +314,7 @@ fn test_3_2_2_simple_string_expansion_n() {
 
 #[test]
 fn test_3_2_2_simple_string_expansion_o() {
-    // let v = test_values();
+    let v = test_values();
 
     let t = UriTemplateBuilder::new()
         .component(None, |c|
@@ -322,12 +322,12 @@ fn test_3_2_2_simple_string_expansion_o() {
         )
         .into_uri_template();
     assert_eq!(t.to_template_string(), "{keys}");
-    // assert_eq!(t.to_string_with_values(&v), "comma,%2C,dot,.,semi,%3B");
+    assert_eq!(t.to_string_with_values(&v), "comma,%2C,dot,.,semi,%3B");
 }
 
 #[test]
 fn test_3_2_2_simple_string_expansion_p() {
-    // let v = test_values();
+    let v = test_values();
 
     let t = UriTemplateBuilder::new()
         .component(None, |c|
@@ -335,5 +335,5 @@ fn test_3_2_2_simple_string_expansion_p() {
         )
         .into_uri_template();
     assert_eq!(t.to_template_string(), "{keys*}");
-    // assert_eq!(t.to_string_with_values(&v), "comma=%2C,dot=.,semi=%3B");
+    assert_eq!(t.to_string_with_values(&v), "comma=%2C,dot=.,semi=%3B");
 }
@@ -0,0 +1,514 @@
+//! Compile-time checked URI templates.
+//!
+//! ```
+//! extern crate uritemplates;
+//! extern crate uritemplates_macros;
+//!
+//! use uritemplates_macros::uri_template;
+//!
+//! # fn main() {
+//! let t = uri_template!("/users/{id}{?fields*}");
+//! assert_eq!(t.to_template_string(), "/users/{id}{?fields*}");
+//! # }
+//! ```
+//!
+//! `#[derive(UriTemplateArgs)]` ties a struct to a template, generating an
+//! `expand` method from its fields. Every template variable must have a
+//! field of the same name and every field must appear in the template:
+//!
+//! ```
+//! extern crate uritemplates;
+//! #[macro_use]
+//! extern crate uritemplates_macros;
+//!
+//! #[derive(UriTemplateArgs)]
+//! #[uri_template("/users/{id}{?fields*}")]
+//! struct UserLink {
+//!     id: u64,
+//!     fields: Vec<String>,
+//! }
+//!
+//! # fn main() {
+//! let link = UserLink { id: 42, fields: vec!("name".to_string()) };
+//! assert_eq!(link.expand(), "/users/42?fields=name");
+//! # }
+//! ```
+//!
+//! `#[derive(UriTemplateVars)]` lets a struct supply values for expansion
+//! directly, with `#[uritemplate(rename = "...")]`, `#[uritemplate(skip)]`,
+//! `#[uritemplate(flatten)]` and `#[uritemplate(untrusted)]` field
+//! attributes:
+//!
+//! ```
+//! extern crate uritemplates;
+//! #[macro_use]
+//! extern crate uritemplates_macros;
+//!
+//! use std::collections::BTreeMap;
+//! use uritemplates::UriTemplate;
+//!
+//! #[derive(UriTemplateVars)]
+//! struct Paging {
+//!     page: u32,
+//! }
+//!
+//! #[derive(UriTemplateVars)]
+//! struct Search {
+//!     #[uritemplate(rename = "q")]
+//!     query: String,
+//!     filters: BTreeMap<String, String>,
+//!     #[uritemplate(flatten)]
+//!     paging: Paging,
+//!     #[uritemplate(skip)]
+//!     cache_key: u64,
+//! }
+//!
+//! # fn main() {
+//! let mut filters = BTreeMap::new();
+//! filters.insert("lang".to_string(), "en".to_string());
+//! let search = Search { query: "rust".to_string(), filters, paging: Paging { page: 2 }, cache_key: 0 };
+//!
+//! let t = UriTemplate::parse("/search{?q,page,filters*}").unwrap();
+//! assert_eq!(t.to_string_with_values(&search), "/search?q=rust&page=2&lang=en");
+//! # }
+//! ```
+//!
+//! `#[derive(FromUriTemplateValues)]` converts the values of a match back
+//! into a struct for `UriTemplate::match_into`, taking the same field
+//! attributes. Fields are converted with `FromUriTemplateValue`, or with
+//! `FromStr` when marked `#[uritemplate(from_str)]`, and errors name the
+//! variable that failed:
+//!
+//! ```
+//! extern crate uritemplates;
+//! #[macro_use]
+//! extern crate uritemplates_macros;
+//!
+//! use std::str::FromStr;
+//! use uritemplates::{UriTemplate, UriTemplateMatchError};
+//!
+//! #[derive(Debug,PartialEq)]
+//! enum Format { Json, Csv }
+//!
+//! impl FromStr for Format {
+//!     type Err = String;
+//!     fn from_str(s: &str) -> Result<Format, String> {
+//!         match s {
+//!             "json" => Ok(Format::Json),
+//!             "csv" => Ok(Format::Csv),
+//!             _ => Err(format!("unknown format {:?}", s)),
+//!         }
+//!     }
+//! }
+//!
+//! #[derive(FromUriTemplateValues)]
+//! struct Report {
+//!     id: u64,
+//!     #[uritemplate(from_str)]
+//!     format: Format,
+//!     page: Option<u32>,
+//! }
+//!
+//! # fn main() {
+//! let t = UriTemplate::parse("/reports/{id}{.format}{?page}").unwrap();
+//! let report: Report = t.match_into("/reports/7.csv?page=2").unwrap();
+//! assert_eq!((report.id, report.format, report.page), (7, Format::Csv, Some(2)));
+//!
+//! let error = t.match_into::<Report>("/reports/7.xml").err().unwrap();
+//! assert_eq!(error, UriTemplateMatchError::InvalidValue("format".to_string(), "unknown format \"xml\"".to_string()));
+//! # }
+//! ```
+//!
+//! Malformed templates are rejected when the crate is compiled:
+//!
+//! ```compile_fail
+//! extern crate uritemplates;
+//! extern crate uritemplates_macros;
+//!
+//! use uritemplates_macros::uri_template;
+//!
+//! # fn main() {
+//! let t = uri_template!("/users/{id");
+//! # }
+//! ```
+//!
+//! as are misspelled or missing variables:
+//!
+//! ```compile_fail
+//! extern crate uritemplates;
+//! #[macro_use]
+//! extern crate uritemplates_macros;
+//!
+//! #[derive(UriTemplateArgs)]
+//! #[uri_template("/users/{id}")]
+//! struct UserLink {
+//!     user_id: u64,
+//! }
+//! # fn main() {}
+//! ```
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+extern crate uritemplates;
+
+use proc_macro2::{Span, TokenStream};
+use syn::{Data, DeriveInput, Fields, LitStr, PathArguments, Type};
+
+use uritemplates::{UriTemplate, UriTemplateComponent, UriTemplateOperator, UriTemplateParseError};
+
+
+/// Parses a URI template at compile time and expands to the equivalent
+/// `UriTemplateBuilder` chain, evaluating to a `UriTemplate`.
+#[proc_macro]
+pub fn uri_template(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let template = syn::parse_macro_input!(input as LitStr);
+    match UriTemplate::parse(&template.value()) {
+        Ok(t) => builder_tokens(&t).into(),
+        Err(e) => syn::Error::new(error_span(&template, &e), format!("invalid URI template: {}", e))
+            .to_compile_error()
+            .into(),
+    }
+}
+
+
+/// Generates `uri_template()` and `expand(&self)` for a struct whose fields
+/// are exactly the variables of the template given in `#[uri_template("...")]`.
+#[proc_macro_derive(UriTemplateArgs, attributes(uri_template))]
+pub fn derive_uri_template_args(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match uri_template_args(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn uri_template_args(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let attribute = input.attrs.iter().find(|a| a.path().is_ident("uri_template"))
+        .ok_or_else(|| syn::Error::new(input.ident.span(), "missing #[uri_template(\"...\")] attribute"))?;
+    let template = attribute.parse_args::<LitStr>()?;
+    let parsed = UriTemplate::parse(&template.value())
+        .map_err(|e| syn::Error::new(error_span(&template, &e), format!("invalid URI template: {}", e)))?;
+
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => return Err(syn::Error::new(input.ident.span(), "UriTemplateArgs requires a struct with named fields")),
+        },
+        _ => return Err(syn::Error::new(input.ident.span(), "UriTemplateArgs requires a struct with named fields")),
+    };
+
+    let names = variable_names(&parsed);
+    let mut errors: Vec<syn::Error> = vec!();
+    for field in fields.iter() {
+        let ident = field.ident.as_ref().unwrap();
+        if !names.iter().any(|n| ident == n) {
+            errors.push(syn::Error::new(ident.span(), format!("field `{}` is not a variable of the template", ident)));
+        }
+    }
+    for name in names.iter() {
+        if !fields.iter().any(|f| f.ident.as_ref().unwrap() == name) {
+            errors.push(syn::Error::new(template.span(), format!("template variable `{}` has no matching field", name)));
+        }
+    }
+    if let Some(mut error) = errors.pop() {
+        for e in errors {
+            error.combine(e);
+        }
+        return Err(error);
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let builder = builder_tokens(&parsed);
+    let setters = fields.iter().map(|f| {
+        let ident = f.ident.as_ref().unwrap();
+        let name = ident.to_string();
+        quote!(values.set_typed(#name, &self.#ident);)
+    });
+    Ok(quote! {
+        impl #impl_generics #ident #ty_generics #where_clause {
+            pub fn uri_template() -> ::uritemplates::UriTemplate {
+                #builder
+            }
+
+            pub fn expand(&self) -> ::std::string::String {
+                let mut values = ::uritemplates::UriTemplateValues::new();
+                #(#setters)*
+                Self::uri_template().to_string_with_values(&values)
+            }
+        }
+    })
+}
+
+/// Implements `UriTemplateVars` for a struct with named fields, looking up
+/// each field by name through `ToUriTemplateValue`.
+#[proc_macro_derive(UriTemplateVars, attributes(uritemplate))]
+pub fn derive_uri_template_vars(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match uri_template_vars(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+enum FieldMode {
+    // The variable name, whether the value is untrusted and whether it is
+    // converted from a match with `FromStr`.
+    Value(String, bool, bool),
+    Skip,
+    Flatten,
+}
+
+fn field_mode(field: &syn::Field) -> syn::Result<FieldMode> {
+    let mut mode = FieldMode::Value(field.ident.as_ref().unwrap().to_string(), false, false);
+    let mut untrusted = false;
+    let mut from_str = false;
+    for attribute in field.attrs.iter().filter(|a| a.path().is_ident("uritemplate")) {
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                let name: LitStr = meta.value()?.parse()?;
+                mode = FieldMode::Value(name.value(), false, false);
+            } else if meta.path.is_ident("skip") {
+                mode = FieldMode::Skip;
+            } else if meta.path.is_ident("flatten") {
+                mode = FieldMode::Flatten;
+            } else if meta.path.is_ident("untrusted") {
+                untrusted = true;
+            } else if meta.path.is_ident("from_str") {
+                from_str = true;
+            } else {
+                return Err(meta.error("expected `rename`, `skip`, `flatten`, `untrusted` or `from_str`"));
+            }
+            Ok(())
+        })?;
+    }
+    match mode {
+        FieldMode::Value(name, _, _) => Ok(FieldMode::Value(name, untrusted, from_str)),
+        _ if untrusted => Err(syn::Error::new(field.ident.as_ref().unwrap().span(), "`untrusted` cannot be combined with `skip` or `flatten`")),
+        _ if from_str => Err(syn::Error::new(field.ident.as_ref().unwrap().span(), "`from_str` cannot be combined with `skip` or `flatten`")),
+        mode => Ok(mode),
+    }
+}
+
+fn uri_template_vars(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => return Err(syn::Error::new(input.ident.span(), "UriTemplateVars requires a struct with named fields")),
+        },
+        _ => return Err(syn::Error::new(input.ident.span(), "UriTemplateVars requires a struct with named fields")),
+    };
+
+    let mut names: Vec<String> = vec!();
+    let mut values: Vec<TokenStream> = vec!();
+    let mut flattened: Vec<TokenStream> = vec!();
+    let mut untrusted: Vec<TokenStream> = vec!();
+    let mut flattened_untrusted: Vec<TokenStream> = vec!();
+    for field in fields.iter() {
+        let ident = field.ident.as_ref().unwrap();
+        match field_mode(field)? {
+            FieldMode::Value(name, is_untrusted, _) => {
+                untrusted.push(quote!(#name => #is_untrusted,));
+                if names.contains(&name) {
+                    return Err(syn::Error::new(ident.span(), format!("duplicate variable name `{}`", name)));
+                }
+                values.push(quote! {
+                    #name => ::uritemplates::ToUriTemplateValue::to_uri_template_value(&self.#ident).map(::std::borrow::Cow::Owned),
+                });
+                names.push(name);
+            },
+            FieldMode::Skip => (),
+            FieldMode::Flatten => {
+                flattened.push(quote! {
+                    if let ::std::option::Option::Some(value) = ::uritemplates::UriTemplateVars::uri_template_value(&self.#ident, name) {
+                        return ::std::option::Option::Some(value);
+                    }
+                });
+                flattened_untrusted.push(quote! {
+                    if ::uritemplates::UriTemplateVars::uri_template_value(&self.#ident, name).is_some() {
+                        return ::uritemplates::UriTemplateVars::is_untrusted(&self.#ident, name);
+                    }
+                });
+            },
+        }
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::uritemplates::UriTemplateVars for #ident #ty_generics #where_clause {
+            fn uri_template_value(&self, name: &str) -> ::std::option::Option<::std::borrow::Cow<'_, ::uritemplates::UriTemplateValue>> {
+                match name {
+                    #(#values)*
+                    _ => {
+                        #(#flattened)*
+                        ::std::option::Option::None
+                    },
+                }
+            }
+
+            fn is_untrusted(&self, name: &str) -> bool {
+                match name {
+                    #(#untrusted)*
+                    _ => {
+                        #(#flattened_untrusted)*
+                        false
+                    },
+                }
+            }
+        }
+    })
+}
+
+
+/// Generates a `FromUriTemplateValues` impl for a struct with named fields,
+/// converting each field with `FromUriTemplateValue`, or with `FromStr` for
+/// fields marked `#[uritemplate(from_str)]` (parsing the inner type of an
+/// `Option`, which is `None` when undefined). Takes the same field attributes
+/// as `UriTemplateVars`; skipped fields are set to their `Default`.
+#[proc_macro_derive(FromUriTemplateValues, attributes(uritemplate))]
+pub fn derive_from_uri_template_values(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match from_uri_template_values(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn from_uri_template_values(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => return Err(syn::Error::new(input.ident.span(), "FromUriTemplateValues requires a struct with named fields")),
+        },
+        _ => return Err(syn::Error::new(input.ident.span(), "FromUriTemplateValues requires a struct with named fields")),
+    };
+
+    let mut names: Vec<String> = vec!();
+    let mut values: Vec<TokenStream> = vec!();
+    for field in fields.iter() {
+        let ident = field.ident.as_ref().unwrap();
+        let value = match field_mode(field)? {
+            FieldMode::Value(name, _, from_str) => {
+                if names.contains(&name) {
+                    return Err(syn::Error::new(ident.span(), format!("duplicate variable name `{}`", name)));
+                }
+                let (convert, undefined) = if from_str && is_option(&field.ty) {
+                    (quote!(::uritemplates::UriTemplateValue::parse(&value).map(::std::option::Option::Some)),
+                     quote!(::std::option::Option::Some(::std::option::Option::None)))
+                } else if from_str {
+                    (quote!(::uritemplates::UriTemplateValue::parse(&value)),
+                     quote!(::std::option::Option::None))
+                } else {
+                    (quote!(::uritemplates::FromUriTemplateValue::from_uri_template_value(&value)),
+                     quote!(::uritemplates::FromUriTemplateValue::from_undefined()))
+                };
+                let value = quote! {
+                    match ::uritemplates::UriTemplateVars::uri_template_value(values, #name) {
+                        ::std::option::Option::Some(value) => #convert
+                            .map_err(|e| ::uritemplates::UriTemplateMatchError::InvalidValue(#name.to_string(), e))?,
+                        ::std::option::Option::None => #undefined
+                            .ok_or_else(|| ::uritemplates::UriTemplateMatchError::MissingValue(#name.to_string()))?,
+                    }
+                };
+                names.push(name);
+                value
+            },
+            FieldMode::Skip => quote!(::std::default::Default::default()),
+            FieldMode::Flatten => quote!(::uritemplates::FromUriTemplateValues::from_uri_template_values(values)?),
+        };
+        values.push(quote!(#ident: #value,));
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::uritemplates::FromUriTemplateValues for #ident #ty_generics #where_clause {
+            fn from_uri_template_values<V: ::uritemplates::UriTemplateVars + ?Sized>(values: &V) -> ::std::result::Result<Self, ::uritemplates::UriTemplateMatchError> {
+                ::std::result::Result::Ok(#ident {
+                    #(#values)*
+                })
+            }
+        }
+    })
+}
+
+// Whether `ty` is written as `Option<...>`, so that a `from_str` field
+// parses the inner type and an undefined variable converts to `None`.
+fn is_option(ty: &Type) -> bool {
+    match *ty {
+        Type::Path(ref path) if path.qself.is_none() => path.path.segments.last().map(|segment| {
+            segment.ident == "Option" && matches!(segment.arguments, PathArguments::AngleBracketed(_))
+        }).unwrap_or(false),
+        _ => false,
+    }
+}
+
+fn variable_names(template: &UriTemplate) -> Vec<String> {
+    let mut names: Vec<String> = vec!();
+    for component in template.components() {
+        if let UriTemplateComponent::Variable(_, ref variables) = *component {
+            for v in variables {
+                if !names.iter().any(|n| n == v.name()) {
+                    names.push(v.name().to_string());
+                }
+            }
+        }
+    }
+    names
+}
+
+
+// Points at the offending character where the literal's source text maps
+// directly onto its value, and at the whole literal otherwise.
+fn error_span(template: &LitStr, error: &UriTemplateParseError) -> Span {
+    let token = template.token();
+    let source = token.to_string();
+    let value = template.value();
+    if source.len() != value.len() + 2 || source[1..source.len() - 1] != value[..] {
+        return template.span();
+    }
+    let position = error.position() + 1;
+    let end = source[position..].chars().next().map(|c| position + c.len_utf8()).unwrap_or(position);
+    token.subspan(position..end).unwrap_or_else(|| template.span())
+}
+
+fn operator_tokens(operator: Option<UriTemplateOperator>) -> TokenStream {
+    let operator = match operator {
+        None => return quote!(::std::option::Option::None),
+        Some(UriTemplateOperator::ReservedCharacter) => quote!(ReservedCharacter),
+        Some(UriTemplateOperator::Fragment) => quote!(Fragment),
+        Some(UriTemplateOperator::PathExtension) => quote!(PathExtension),
+        Some(UriTemplateOperator::PathComponent) => quote!(PathComponent),
+        Some(UriTemplateOperator::PathParameter) => quote!(PathParameter),
+        Some(UriTemplateOperator::QueryParameter) => quote!(QueryParameter),
+        Some(UriTemplateOperator::QueryContinuation) => quote!(QueryContinuation),
+    };
+    quote!(::std::option::Option::Some(::uritemplates::UriTemplateOperator::#operator))
+}
+
+fn builder_tokens(template: &UriTemplate) -> TokenStream {
+    let components = template.components().iter().map(|component| {
+        match *component {
+            UriTemplateComponent::Literal(ref value) => quote!(.literal(#value)),
+            UriTemplateComponent::Variable(operator, ref variables) => {
+                let operator = operator_tokens(operator);
+                let variables = variables.iter().map(|v| {
+                    let name = v.name();
+                    let modifier = match (v.prefix(), v.explode()) {
+                        (None, false) => quote!(::std::option::Option::None),
+                        (Some(prefix), false) => quote!(::std::option::Option::Some(::uritemplates::UriTemplateModifier::Prefix(#prefix))),
+                        (None, true) => quote!(::std::option::Option::Some(::uritemplates::UriTemplateModifier::Explode)),
+                        (Some(prefix), true) => quote!(::std::option::Option::Some(::uritemplates::UriTemplateModifier::ExplodePrefix(#prefix))),
+                    };
+                    quote!(.variable(#name, #modifier))
+                });
+                quote!(.component(#operator, |c| c #(#variables)*))
+            },
+        }
+    });
+    quote!(::uritemplates::UriTemplateBuilder::new() #(#components)* .into_uri_template())
+}
@@ -0,0 +1,115 @@
+extern crate uritemplates;
+#[macro_use]
+extern crate uritemplates_macros;
+
+use std::str::FromStr;
+
+use uritemplates::{FromUriTemplateValues, UriTemplate, UriTemplateMatchError, UriTemplateParseOptions, UriTemplateValues};
+
+
+#[derive(Debug,PartialEq)]
+enum Sort {
+    Asc,
+    Desc,
+}
+
+impl FromStr for Sort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Sort, String> {
+        match s {
+            "asc" => Ok(Sort::Asc),
+            "desc" => Ok(Sort::Desc),
+            _ => Err(format!("expected asc or desc, not {:?}", s)),
+        }
+    }
+}
+
+#[derive(Debug,PartialEq,FromUriTemplateValues)]
+struct Paging {
+    page: Option<u32>,
+    #[uritemplate(from_str)]
+    sort: Sort,
+}
+
+#[derive(Debug,PartialEq,FromUriTemplateValues)]
+struct Search {
+    #[uritemplate(rename = "q")]
+    query: String,
+    tags: Vec<String>,
+    #[uritemplate(flatten)]
+    paging: Paging,
+    #[uritemplate(skip)]
+    cache_key: u64,
+}
+
+fn template() -> UriTemplate {
+    UriTemplate::parse("/search{?q,page,sort,tags*}").unwrap()
+}
+
+#[test]
+fn test_match_into() {
+    let search: Search = template().match_into("/search?q=Hello%20World&sort=asc&tags=red&tags=green").unwrap();
+    assert_eq!(search, Search {
+        query: "Hello World".to_string(),
+        tags: vec!("red".to_string(), "green".to_string()),
+        paging: Paging { page: None, sort: Sort::Asc },
+        cache_key: 0,
+    });
+}
+
+#[test]
+fn test_errors() {
+    assert_eq!(template().match_into::<Search>("/search?q=a&page=x&sort=asc"),
+        Err(UriTemplateMatchError::InvalidValue("page".to_string(), "invalid digit found in string".to_string())));
+    assert_eq!(template().match_into::<Search>("/search?q=a&sort=up"),
+        Err(UriTemplateMatchError::InvalidValue("sort".to_string(), "expected asc or desc, not \"up\"".to_string())));
+    assert_eq!(template().match_into::<Search>("/search?q=a"), Err(UriTemplateMatchError::MissingValue("sort".to_string())));
+    assert_eq!(template().match_into::<Search>("/other"), Err(UriTemplateMatchError::NoMatch));
+}
+
+#[test]
+fn test_constrained_match() {
+    let options = UriTemplateParseOptions::new().strict(false);
+    let t = UriTemplate::parse_with_options("/items{?page:int,sort:(asc|desc)}", &options).unwrap();
+    assert_eq!(t.match_into("/items?page=3&sort=desc"), Ok(Paging { page: Some(3), sort: Sort::Desc }));
+    assert_eq!(t.match_into::<Paging>("/items?page=3&sort=up"), Err(UriTemplateMatchError::NoMatch));
+}
+
+#[test]
+fn test_from_values() {
+    let mut values = UriTemplateValues::new();
+    values.set("sort", "desc");
+    assert_eq!(Paging::from_uri_template_values(&values), Ok(Paging { page: None, sort: Sort::Desc }));
+}
+
+#[derive(Debug,PartialEq,FromUriTemplateValues)]
+struct Report {
+    format: String,
+}
+
+#[test]
+fn test_default_value() {
+    let options = UriTemplateParseOptions::new().strict(false);
+    let t = UriTemplate::parse_with_options("/report{?format=json}", &options).unwrap();
+    assert_eq!(t.match_into("/report"), Ok(Report { format: "json".to_string() }));
+    assert_eq!(t.match_into("/report?format=csv"), Ok(Report { format: "csv".to_string() }));
+}
+
+#[derive(Debug,PartialEq,FromUriTemplateValues)]
+struct Listing {
+    #[uritemplate(from_str)]
+    sort: Option<Sort>,
+    ids: Vec<u32>,
+}
+
+#[test]
+fn test_optional_from_str() {
+    let t = UriTemplate::parse("/items{?sort,ids}").unwrap();
+    assert_eq!(t.match_into("/items"), Ok(Listing { sort: None, ids: vec!() }));
+    assert_eq!(t.match_into("/items?sort=asc&ids=1,2"), Ok(Listing { sort: Some(Sort::Asc), ids: vec!(1, 2) }));
+    assert_eq!(t.match_into::<Listing>("/items?sort=up"),
+        Err(UriTemplateMatchError::InvalidValue("sort".to_string(), "expected asc or desc, not \"up\"".to_string())));
+    assert_eq!(t.match_into::<Listing>("/items?ids=1%2C2"),
+        Err(UriTemplateMatchError::InvalidValue("ids".to_string(), "invalid digit found in string".to_string())));
+}
@@ -0,0 +1,46 @@
+extern crate uritemplates;
+extern crate uritemplates_macros;
+
+use uritemplates::{UriTemplate, UriTemplateValues};
+use uritemplates_macros::uri_template;
+
+
+#[test]
+fn test_literal() {
+    let t = uri_template!("http://example.com/");
+    assert_eq!(t, UriTemplate::parse("http://example.com/").unwrap());
+}
+
+#[test]
+fn test_expressions() {
+    let t = uri_template!("/users/{id}{/path*}{?fields,hash:7}{#frag}");
+    assert_eq!(t.to_template_string(), "/users/{id}{/path*}{?fields,hash:7}{#frag}");
+    assert_eq!(t, UriTemplate::parse("/users/{id}{/path*}{?fields,hash:7}{#frag}").unwrap());
+}
+
+#[test]
+fn test_expansion() {
+    let t = uri_template!("/users/{id}{?fields*}");
+
+    let mut v = UriTemplateValues::new();
+    v.set("id", "42");
+    v.set("fields", ["name", "email"].as_ref());
+    assert_eq!(t.to_string_with_values(&v), "/users/42?fields=name&fields=email");
+}
+
+mod shadowed {
+    use uritemplates::UriTemplate;
+    use uritemplates_macros::uri_template;
+
+    #[allow(dead_code, non_upper_case_globals)]
+    const None: () = ();
+
+    #[allow(dead_code, non_snake_case)]
+    fn Some<T>(_: T) {}
+
+    #[test]
+    fn test_shadowed_option() {
+        let t = uri_template!("/{a}{/b*}{?c:3}");
+        assert_eq!(t, UriTemplate::parse("/{a}{/b*}{?c:3}").unwrap());
+    }
+}
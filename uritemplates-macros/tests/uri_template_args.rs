@@ -0,0 +1,30 @@
+extern crate uritemplates;
+#[macro_use]
+extern crate uritemplates_macros;
+
+
+#[derive(UriTemplateArgs)]
+#[uri_template("/users/{id}/posts{/post}{?fields*,page}")]
+struct PostLink<'a> {
+    id: u64,
+    post: Option<&'a str>,
+    fields: Vec<&'a str>,
+    page: Option<u32>,
+}
+
+#[test]
+fn test_template() {
+    assert_eq!(PostLink::uri_template().to_template_string(), "/users/{id}/posts{/post}{?fields*,page}");
+}
+
+#[test]
+fn test_expand_required() {
+    let link = PostLink { id: 7, post: None, fields: vec!(), page: None };
+    assert_eq!(link.expand(), "/users/7/posts");
+}
+
+#[test]
+fn test_expand_optional() {
+    let link = PostLink { id: 7, post: Some("hello world"), fields: vec!("title", "body"), page: Some(2) };
+    assert_eq!(link.expand(), "/users/7/posts/hello%20world?fields=title&fields=body&page=2");
+}
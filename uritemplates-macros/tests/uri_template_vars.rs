@@ -0,0 +1,89 @@
+extern crate uritemplates;
+#[macro_use]
+extern crate uritemplates_macros;
+
+use std::collections::BTreeMap;
+
+use uritemplates::{UriTemplate, UriTemplateValue, UriTemplateValues, UriTemplateVars};
+
+
+#[derive(UriTemplateVars)]
+struct Paging {
+    page: Option<u32>,
+    size: u32,
+}
+
+#[derive(UriTemplateVars)]
+struct Search<'a> {
+    #[uritemplate(rename = "q")]
+    query: &'a str,
+    tags: Vec<&'a str>,
+    keys: BTreeMap<&'a str, &'a str>,
+    #[uritemplate(flatten)]
+    paging: Paging,
+    #[uritemplate(skip)]
+    #[allow(dead_code)]
+    secret: &'a str,
+}
+
+fn search<'a>() -> Search<'a> {
+    let mut keys = BTreeMap::new();
+    keys.insert("semi", ";");
+    keys.insert("dot", ".");
+    Search {
+        query: "Hello World!",
+        tags: vec!("red", "green"),
+        keys,
+        paging: Paging { page: None, size: 20 },
+        secret: "hunter2",
+    }
+}
+
+#[test]
+fn test_lookup() {
+    let s = search();
+    assert!(s.uri_template_value("q").unwrap().into_owned() == UriTemplateValue::String("Hello World!".to_string()));
+    assert!(s.uri_template_value("query").is_none());
+    assert!(s.uri_template_value("secret").is_none());
+    assert!(s.uri_template_value("page").is_none());
+    assert!(s.uri_template_value("size").unwrap().into_owned() == UriTemplateValue::String("20".to_string()));
+}
+
+#[test]
+fn test_expansion() {
+    let s = search();
+    let t = UriTemplate::parse("/search{?q,tags,page,size,secret}").unwrap();
+    assert_eq!(t.to_string_with_values(&s), "/search?q=Hello%20World%21&tags=red,green&size=20");
+}
+
+#[test]
+fn test_expansion_associative_array() {
+    let s = search();
+    let t = UriTemplate::parse("{;keys}{?keys*}").unwrap();
+    assert_eq!(t.to_string_with_values(&s), ";keys=dot,.,semi,%3B?dot=.&semi=%3B");
+}
+
+#[derive(UriTemplateVars)]
+struct Redirect {
+    base: &'static str,
+    #[uritemplate(untrusted, rename = "next")]
+    target: String,
+    #[uritemplate(flatten)]
+    extra: UriTemplateValues,
+}
+
+#[test]
+fn test_untrusted() {
+    let mut extra = UriTemplateValues::new();
+    extra.set_untrusted("state", "a/b");
+    extra.set("lang", "en/gb");
+    let r = Redirect { base: "https://example.com", target: "@evil.com".to_string(), extra };
+    assert!(r.is_untrusted("next"));
+    assert!(r.is_untrusted("state"));
+    assert!(!r.is_untrusted("base"));
+    assert!(!r.is_untrusted("lang"));
+    assert!(!r.is_untrusted("missing"));
+
+    let t = UriTemplate::parse("{+base}/login{#next}{+state,lang}").unwrap();
+    assert_eq!(t.to_string_with_values(&r), "https://example.com/login#%40evil.coma%2Fb,en/gb");
+}